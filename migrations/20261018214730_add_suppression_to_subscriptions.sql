@@ -0,0 +1,6 @@
+-- Track addresses we must stop sending to. A subscriber is suppressed
+-- whenever suppressed_at is set.
+ALTER TABLE subscriptions
+    ADD COLUMN soft_bounce_count INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN suppressed_at timestamptz NULL,
+    ADD COLUMN suppression_reason TEXT NULL;
@@ -0,0 +1,12 @@
+-- Opens and clicks recorded by the tracking endpoints
+CREATE TABLE tracking_events (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id),
+    issue_id uuid NOT NULL,
+    kind TEXT NOT NULL,
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX tracking_events_issue_id_idx ON tracking_events (issue_id);
@@ -0,0 +1,11 @@
+-- Emails we couldn't hand to the email provider yet, e.g. because its
+-- circuit breaker was open. A background worker sends them later.
+CREATE TABLE queued_emails (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html_body TEXT NOT NULL,
+    text_body TEXT NOT NULL,
+    queued_at timestamptz NOT NULL
+);
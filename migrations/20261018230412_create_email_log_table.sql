@@ -0,0 +1,24 @@
+-- One row per attempt at handing an email to a provider
+CREATE TABLE email_log (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    recipient TEXT NOT NULL,
+    subscriber_id uuid NULL
+        REFERENCES subscriptions(id),
+    -- 'confirmation' or 'issue'. Emails for an issue also have its issue_id.
+    purpose TEXT NOT NULL,
+    issue_id uuid NULL,
+    provider TEXT NULL,
+    -- The provider's ID for the email, webhooks refer to it by this
+    message_id TEXT NULL,
+    status TEXT NOT NULL,
+    error TEXT NULL,
+    logged_at timestamptz NOT NULL
+);
+CREATE INDEX email_log_subscriber_id_idx ON email_log (subscriber_id, logged_at);
+CREATE INDEX email_log_message_id_idx ON email_log (message_id);
+
+-- Queued emails need to end up in the log like any other
+ALTER TABLE queued_emails ADD COLUMN purpose TEXT NOT NULL DEFAULT 'confirmation';
+ALTER TABLE queued_emails ALTER COLUMN purpose DROP DEFAULT;
+ALTER TABLE queued_emails ADD COLUMN issue_id uuid NULL;
@@ -0,0 +1,14 @@
+-- Emails are now written to the outbox in the same transaction as the data they
+-- are about. A background relay sends them, retrying until the provider takes
+-- them or rejects them for good.
+ALTER TABLE queued_emails RENAME TO outbox;
+ALTER TABLE outbox RENAME COLUMN queued_at TO created_at;
+ALTER TABLE outbox ADD COLUMN subscriber_id uuid NULL
+    REFERENCES subscriptions(id);
+ALTER TABLE outbox ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE outbox ADD COLUMN next_attempt_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE outbox ADD COLUMN last_error TEXT NULL;
+-- Set once the provider rejected the email for good. Kept around for inspection,
+-- the relay skips these.
+ALTER TABLE outbox ADD COLUMN failed_at timestamptz NULL;
+CREATE INDEX outbox_pending_idx ON outbox (next_attempt_at) WHERE failed_at IS NULL;
@@ -0,0 +1,3 @@
+-- The language subscribers get their emails and pages in, as a language tag
+-- like 'fr'. Existing subscribers signed up in English.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
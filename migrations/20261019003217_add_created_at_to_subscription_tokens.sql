@@ -0,0 +1,4 @@
+-- Confirmation links expire. Tokens handed out before we tracked this start
+-- their lifetime now.
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,2 @@
+-- Where subscribers signed up, e.g. the embedded widget of a partner site
+ALTER TABLE subscriptions ADD COLUMN source TEXT NULL;
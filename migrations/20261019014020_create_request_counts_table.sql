@@ -0,0 +1,9 @@
+-- Requests counted by the request rate limiter, when instances share their counts
+CREATE TABLE request_counts(
+    key TEXT NOT NULL,
+    window_start timestamptz NOT NULL,
+    window_end timestamptz NOT NULL,
+    hits INTEGER NOT NULL,
+    PRIMARY KEY (key, window_start)
+);
+CREATE INDEX request_counts_window_end ON request_counts (window_end);
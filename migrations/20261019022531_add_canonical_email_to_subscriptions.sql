@@ -0,0 +1,15 @@
+-- The address with case, plus tags and Gmail dots folded away, so the same
+-- mailbox can't subscribe twice under different spellings. `email` keeps the
+-- spelling subscribers typed.
+ALTER TABLE subscriptions ADD COLUMN canonical_email TEXT NULL;
+
+-- Existing subscribers get their lowercased address. Should several share one,
+-- the first to subscribe keeps it and the others stay NULL.
+UPDATE subscriptions SET canonical_email = lower(email)
+WHERE id IN (
+    SELECT DISTINCT ON (lower(email)) id
+    FROM subscriptions
+    ORDER BY lower(email), subscribed_at
+);
+
+CREATE UNIQUE INDEX subscriptions_canonical_email_idx ON subscriptions (canonical_email);
@@ -0,0 +1,62 @@
+-- Backfill canonical_email with the rules of `SubscriberEmail::canonical`, then
+-- require it. Keep PLUS_ADDRESSING_DOMAINS below in sync with the Rust list.
+BEGIN;
+    CREATE TEMPORARY TABLE canonical_emails ON COMMIT DROP AS
+    WITH parts AS (
+        SELECT
+            id,
+            lower(substring(email FROM '^(.*)@[^@]*$')) AS local_part,
+            CASE lower(substring(email FROM '@([^@]*)$'))
+                WHEN 'googlemail.com' THEN 'gmail.com'
+                ELSE lower(substring(email FROM '@([^@]*)$'))
+            END AS domain
+        FROM subscriptions
+    ), untagged AS (
+        SELECT
+            id,
+            CASE WHEN domain IN (
+                'fastmail.com', 'fastmail.fm', 'gmail.com', 'hotmail.com',
+                'icloud.com', 'live.com', 'mac.com', 'me.com', 'msn.com',
+                'outlook.com', 'pm.me', 'proton.me', 'protonmail.ch',
+                'protonmail.com'
+            )
+                THEN split_part(local_part, '+', 1)
+                ELSE local_part
+            END AS local_part,
+            domain
+        FROM parts
+    )
+    SELECT
+        id,
+        CASE domain
+            WHEN 'gmail.com' THEN replace(local_part, '.', '')
+            ELSE local_part
+        END || '@' || domain AS canonical_email
+    FROM untagged;
+
+    -- Subscribers sharing a mailbox are merged into one. A suppressed row wins,
+    -- so we keep not sending to it, then a confirmed one, then the first to
+    -- subscribe. Everything pointing at the others is moved over to it.
+    CREATE TEMPORARY TABLE merged_subscribers ON COMMIT DROP AS
+    SELECT id, first_value(id) OVER mailbox AS kept_id
+    FROM subscriptions JOIN canonical_emails USING (id)
+    WINDOW mailbox AS (
+        PARTITION BY canonical_emails.canonical_email
+        ORDER BY suppressed_at IS NULL, status <> 'confirmed', subscribed_at, id
+    );
+    DELETE FROM merged_subscribers WHERE id = kept_id;
+
+    UPDATE subscription_tokens SET subscriber_id = kept_id
+        FROM merged_subscribers WHERE subscriber_id = merged_subscribers.id;
+    UPDATE tracking_events SET subscriber_id = kept_id
+        FROM merged_subscribers WHERE subscriber_id = merged_subscribers.id;
+    UPDATE email_log SET subscriber_id = kept_id
+        FROM merged_subscribers WHERE subscriber_id = merged_subscribers.id;
+    UPDATE outbox SET subscriber_id = kept_id
+        FROM merged_subscribers WHERE subscriber_id = merged_subscribers.id;
+    DELETE FROM subscriptions WHERE id IN (SELECT id FROM merged_subscribers);
+
+    UPDATE subscriptions SET canonical_email = canonical_emails.canonical_email
+        FROM canonical_emails WHERE subscriptions.id = canonical_emails.id;
+    ALTER TABLE subscriptions ALTER COLUMN canonical_email SET NOT NULL;
+COMMIT;
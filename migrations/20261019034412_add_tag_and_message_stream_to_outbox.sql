@@ -0,0 +1,5 @@
+-- Outbox emails keep what the provider needs to file them, like any other
+-- EmailMessage. Until now the outbox only held confirmation emails.
+ALTER TABLE outbox ADD COLUMN tag TEXT NULL;
+ALTER TABLE outbox ADD COLUMN message_stream TEXT NULL;
+UPDATE outbox SET tag = 'confirmation' WHERE purpose = 'confirmation';
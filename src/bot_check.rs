@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    csrf::TOKEN_LIFETIME_HOURS,
+    signing::{self, Purpose},
+};
+
+/// When a form was rendered. Signed into a hidden field, so we can tell how long
+/// it took to fill in.
+#[derive(Serialize, Deserialize)]
+struct RenderedAt {
+    rendered_at: DateTime<Utc>,
+}
+
+/// What we made of a form submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotVerdict {
+    Human,
+    /// The honeypot field was filled in
+    FilledHoneypot,
+    /// Submitted faster than people can type
+    TooFast,
+    /// The render timestamp is missing, forged, garbled or older than forms live
+    InvalidTimestamp,
+}
+
+impl BotVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Human => "human",
+            Self::FilledHoneypot => "filled_honeypot",
+            Self::TooFast => "too_fast",
+            Self::InvalidTimestamp => "invalid_timestamp",
+        }
+    }
+
+    pub fn is_bot(&self) -> bool {
+        *self != Self::Human
+    }
+}
+
+/// Tells bots from people filling in our forms, with a honeypot field and the
+/// time it took to submit the form.
+///
+/// Bots are meant to be told they succeeded, so they don't adapt.
+#[derive(Debug, Clone)]
+pub struct BotCheck {
+    /// Forms submitted quicker than this after being rendered are from bots
+    pub minimum_time_to_submit: Duration,
+}
+
+impl BotCheck {
+    /// The value for the hidden timestamp field of a form being rendered now.
+    pub fn timestamp(secret: &Secret<String>) -> String {
+        signing::sign(
+            Purpose::FormTimestamp,
+            &RenderedAt {
+                rendered_at: Utc::now(),
+            },
+            secret,
+        )
+    }
+
+    /// The value for the hidden timestamp field of a form shown again after a
+    /// rejected submission: `previous`, so fixing the form doesn't count as filling
+    /// it in too fast. Forms that expired, or whose timestamp isn't ours, start over.
+    pub fn carry_over(previous: Option<&str>, secret: &Secret<String>) -> String {
+        match previous {
+            Some(previous) if age(previous, secret, Utc::now()).is_some() => previous.to_string(),
+            _ => Self::timestamp(secret),
+        }
+    }
+
+    /// Checks a submission with the given `honeypot` and `timestamp` field values.
+    /// Forms hosted elsewhere may have neither, their timestamp is only checked
+    /// when `require_timestamp` is set.
+    ///
+    /// The verdict and the time the form took to submit are recorded, to tune the
+    /// minimum time to submit against.
+    pub fn check(
+        &self,
+        honeypot: Option<&str>,
+        timestamp: Option<&str>,
+        require_timestamp: bool,
+        secret: &Secret<String>,
+    ) -> BotVerdict {
+        self.check_at(honeypot, timestamp, require_timestamp, secret, Utc::now())
+    }
+
+    fn check_at(
+        &self,
+        honeypot: Option<&str>,
+        timestamp: Option<&str>,
+        require_timestamp: bool,
+        secret: &Secret<String>,
+        now: DateTime<Utc>,
+    ) -> BotVerdict {
+        let time_to_submit = timestamp.map(|timestamp| age(timestamp, secret, now));
+
+        let verdict = if honeypot.is_some_and(|value| !value.trim().is_empty()) {
+            BotVerdict::FilledHoneypot
+        } else {
+            match time_to_submit {
+                Some(Some(time)) if time < self.minimum_time_to_submit => BotVerdict::TooFast,
+                Some(Some(_)) => BotVerdict::Human,
+                Some(None) => BotVerdict::InvalidTimestamp,
+                None if require_timestamp => BotVerdict::InvalidTimestamp,
+                None => BotVerdict::Human,
+            }
+        };
+
+        tracing::info!(
+            bot_verdict = verdict.as_str(),
+            seconds_to_submit = time_to_submit.flatten().map(|time| time.as_secs_f64()),
+            "Checked submission for bots"
+        );
+        verdict
+    }
+}
+
+/// How long ago the form with `timestamp` was rendered, if the timestamp is ours
+/// and the form hasn't expired. Forms live as long as their CSRF token.
+fn age(timestamp: &str, secret: &Secret<String>, now: DateTime<Utc>) -> Option<Duration> {
+    let timestamp =
+        signing::verify::<RenderedAt>(Purpose::FormTimestamp, timestamp, secret).ok()?;
+    let age = now - timestamp.rendered_at;
+    if age > chrono::Duration::hours(TOKEN_LIFETIME_HOURS) {
+        return None;
+    }
+    age.to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use secrecy::Secret;
+
+    use super::{BotCheck, BotVerdict};
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    fn bot_check() -> BotCheck {
+        BotCheck {
+            minimum_time_to_submit: Duration::from_secs(3),
+        }
+    }
+
+    fn check_after(seconds: i64, honeypot: Option<&str>) -> BotVerdict {
+        let timestamp = BotCheck::timestamp(&secret());
+        bot_check().check_at(
+            honeypot,
+            Some(&timestamp),
+            true,
+            &secret(),
+            Utc::now() + chrono::Duration::seconds(seconds),
+        )
+    }
+
+    #[test]
+    fn people_taking_their_time_pass() {
+        assert_eq!(check_after(10, None), BotVerdict::Human);
+        assert_eq!(check_after(10, Some("  ")), BotVerdict::Human);
+    }
+
+    #[test]
+    fn filling_the_honeypot_gives_bots_away() {
+        assert_eq!(
+            check_after(10, Some("https://spam.example")),
+            BotVerdict::FilledHoneypot
+        );
+    }
+
+    #[test]
+    fn submitting_too_fast_gives_bots_away() {
+        assert_eq!(check_after(1, None), BotVerdict::TooFast);
+    }
+
+    #[test]
+    fn timestamps_must_be_ours() {
+        let forged = BotCheck::timestamp(&Secret::new("another-key".to_string()));
+
+        assert_eq!(
+            bot_check().check(None, Some(&forged), false, &secret()),
+            BotVerdict::InvalidTimestamp
+        );
+        assert_eq!(
+            bot_check().check(None, Some("garbage"), false, &secret()),
+            BotVerdict::InvalidTimestamp
+        );
+    }
+
+    #[test]
+    fn timestamps_expire_with_the_csrf_token() {
+        assert_eq!(check_after(2 * 60 * 60 - 1, None), BotVerdict::Human);
+        assert_eq!(
+            check_after(2 * 60 * 60 + 1, None),
+            BotVerdict::InvalidTimestamp
+        );
+    }
+
+    #[test]
+    fn valid_timestamps_are_carried_over_and_others_replaced() {
+        let timestamp = BotCheck::timestamp(&secret());
+        let forged = BotCheck::timestamp(&Secret::new("another-key".to_string()));
+
+        assert_eq!(BotCheck::carry_over(Some(&timestamp), &secret()), timestamp);
+        assert_ne!(BotCheck::carry_over(Some(&forged), &secret()), forged);
+        assert_eq!(
+            bot_check().check(
+                None,
+                Some(&BotCheck::carry_over(None, &secret())),
+                true,
+                &secret()
+            ),
+            BotVerdict::TooFast
+        );
+    }
+
+    #[test]
+    fn timestamps_are_only_required_when_asked() {
+        assert_eq!(
+            bot_check().check(None, None, true, &secret()),
+            BotVerdict::InvalidTimestamp
+        );
+        assert_eq!(
+            bot_check().check(None, None, false, &secret()),
+            BotVerdict::Human
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The state of a `CircuitBreaker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests go through as normal.
+    Closed,
+    /// Too many recent requests failed. Requests fail fast until the breaker has
+    /// cooled down.
+    Open,
+    /// The breaker has cooled down and lets a single probe request through. Its
+    /// outcome decides whether we close or open again.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// When a `CircuitBreaker` should open, and for how long.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerPolicy {
+    /// The share of failed requests in the window, between 0 and 1, at which the
+    /// breaker opens.
+    pub failure_rate_threshold: f64,
+    /// Number of most recent requests the failure rate is computed over
+    pub window_size: usize,
+    /// Don't open before the window holds at least this many requests
+    pub minimum_requests: usize,
+    /// How long to fail fast before letting a probe request through
+    pub open_duration: Duration,
+}
+
+/// Stops us from hammering a service that is down, and saves callers from waiting
+/// on requests that are bound to fail anyway.
+///
+/// Callers ask `try_acquire` for permission before each request, then report back
+/// with `record_success` or `record_failure`.
+pub struct CircuitBreaker {
+    /// The name of the email provider this breaker guards, for logging
+    provider: String,
+    policy: CircuitBreakerPolicy,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    state: CircuitState,
+    /// Outcomes of the most recent requests while closed, `true` for failures
+    window: VecDeque<bool>,
+    /// When we last opened, or last let a probe through
+    since: Instant,
+}
+
+impl CircuitBreaker {
+    /// A closed breaker guarding the email provider called `provider`.
+    pub fn new(provider: String, policy: CircuitBreakerPolicy) -> Self {
+        Self {
+            provider,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                window: VecDeque::with_capacity(policy.window_size),
+                since: Instant::now(),
+            }),
+            policy,
+        }
+    }
+
+    /// The current state of the breaker. An open breaker that has cooled down is
+    /// reported as half open, since the next request will be let through.
+    pub fn state(&self) -> CircuitState {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Open if inner.since.elapsed() >= self.policy.open_duration => {
+                CircuitState::HalfOpen
+            }
+            state => state,
+        }
+    }
+
+    /// Whether a request may go ahead right now.
+    pub fn try_acquire(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => true,
+            // Only one probe at a time. If the probe never reports back, e.g.
+            // because its caller was cancelled, allow another one after a while.
+            CircuitState::Open | CircuitState::HalfOpen
+                if inner.since.elapsed() >= self.policy.open_duration =>
+            {
+                if inner.state == CircuitState::Open {
+                    tracing::info!(
+                        provider = %self.provider,
+                        "Email provider circuit breaker is half open, probing"
+                    );
+                }
+                inner.state = CircuitState::HalfOpen;
+                inner.since = Instant::now();
+                true
+            }
+            CircuitState::Open | CircuitState::HalfOpen => false,
+        }
+    }
+
+    /// Report that a request went through.
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::HalfOpen => {
+                tracing::info!(provider = %self.provider, "Email provider circuit breaker closed");
+                inner.state = CircuitState::Closed;
+                inner.window.clear();
+            }
+            CircuitState::Closed => self.push(&mut inner, false),
+            CircuitState::Open => {}
+        }
+    }
+
+    /// Report that a request failed in a way that suggests the service is unhealthy.
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::HalfOpen => {
+                tracing::warn!(
+                    provider = %self.provider,
+                    "Email provider circuit breaker probe failed, opening again"
+                );
+                self.open(&mut inner);
+            }
+            CircuitState::Closed => {
+                self.push(&mut inner, true);
+                let failures = inner.window.iter().filter(|&&failed| failed).count();
+                let failure_rate = failures as f64 / inner.window.len() as f64;
+                if inner.window.len() >= self.policy.minimum_requests
+                    && failure_rate >= self.policy.failure_rate_threshold
+                {
+                    tracing::warn!(
+                        provider = %self.provider,
+                        failure_rate,
+                        "Email provider circuit breaker opened"
+                    );
+                    self.open(&mut inner);
+                }
+            }
+            CircuitState::Open => {}
+        }
+    }
+
+    fn push(&self, inner: &mut Inner, failed: bool) {
+        if inner.window.len() == self.policy.window_size {
+            inner.window.pop_front();
+        }
+        inner.window.push_back(failed);
+    }
+
+    fn open(&self, inner: &mut Inner) {
+        inner.state = CircuitState::Open;
+        inner.since = Instant::now();
+        inner.window.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CircuitBreaker, CircuitBreakerPolicy, CircuitState};
+
+    fn circuit_breaker(open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "primary".into(),
+            CircuitBreakerPolicy {
+                failure_rate_threshold: 0.5,
+                window_size: 4,
+                minimum_requests: 4,
+                open_duration,
+            },
+        )
+    }
+
+    #[test]
+    fn a_new_breaker_is_closed() {
+        let breaker = circuit_breaker(Duration::from_secs(60));
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn the_breaker_stays_closed_below_the_minimum_number_of_requests() {
+        let breaker = circuit_breaker(Duration::from_secs(60));
+
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn the_breaker_stays_closed_below_the_failure_rate_threshold() {
+        let breaker = circuit_breaker(Duration::from_secs(60));
+
+        breaker.record_failure();
+        for _ in 0..3 {
+            breaker.record_success();
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn the_breaker_opens_at_the_failure_rate_threshold_and_fails_fast() {
+        let breaker = circuit_breaker(Duration::from_secs(60));
+
+        breaker.record_success();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn old_outcomes_fall_out_of_the_window() {
+        let breaker = circuit_breaker(Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        for _ in 0..4 {
+            breaker.record_success();
+        }
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn an_open_breaker_lets_a_single_probe_through_after_cooling_down() {
+        let breaker = circuit_breaker(Duration::from_millis(50));
+        for _ in 0..4 {
+            breaker.record_failure();
+        }
+        assert!(!breaker.try_acquire());
+        std::thread::sleep(Duration::from_millis(60));
+
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_acquire());
+        // The probe hasn't reported back yet, so everyone else keeps failing fast
+        assert!(!breaker.try_acquire());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn a_successful_probe_closes_the_breaker() {
+        let breaker = circuit_breaker(Duration::ZERO);
+        for _ in 0..4 {
+            breaker.record_failure();
+        }
+
+        assert!(breaker.try_acquire());
+        breaker.record_success();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn a_failed_probe_opens_the_breaker_again() {
+        let breaker = circuit_breaker(Duration::from_millis(50));
+        for _ in 0..4 {
+            breaker.record_failure();
+        }
+        std::thread::sleep(Duration::from_millis(60));
+
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+    }
+}
@@ -1,8 +1,23 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
-use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use sqlx::{
+    postgres::{PgConnectOptions, PgSslMode},
+    PgPool,
+};
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    bot_check::BotCheck,
+    circuit_breaker::{CircuitBreaker, CircuitBreakerPolicy},
+    cors::{CorsError, CorsPolicy},
+    dkim::{DkimAlgorithm, DkimError, DkimSigner},
+    domain::{EmailPolicy, EmailSuggester, SubscriberEmail},
+    email_client::{EmailClient, EmailProvider, RetryPolicy},
+    rate_limiter::{RateLimit, SendRateLimiter},
+    request_limiter::{EndpointLimits, LimitStore, LimitedEndpoint, RequestLimit, RequestLimiter},
+    routes::{ConfirmationOptions, PostmarkWebhookOptions},
+};
 
 /// App-wide configuration
 #[derive(Deserialize, Clone)]
@@ -10,6 +25,8 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub postmark_webhook: PostmarkWebhookSettings,
+    pub request_limits: RequestLimitSettings,
 }
 
 #[derive(Deserialize, Clone)]
@@ -20,6 +37,150 @@ pub struct ApplicationSettings {
     pub port: u16,
     /// The base URL to use to build API requests
     pub base_url: String,
+    /// Key for signing values we hand out and need to trust when they come back,
+    /// e.g. tracking tokens and CSRF cookies. Comes from exactly one of
+    /// `hmac_secret`, `hmac_secret_env` or `hmac_secret_file`, see `hmac_secret()`.
+    pub hmac_secret: Option<Secret<String>>,
+    /// The environment variable holding the key
+    pub hmac_secret_env: Option<String>,
+    /// A file holding the key, like a mounted secret
+    pub hmac_secret_file: Option<PathBuf>,
+    /// Where the templates of emails and pages are, relative to the working
+    /// directory
+    pub templates_directory: String,
+    /// How long confirmation links work for
+    pub subscription_token_lifetime_hours: i64,
+    /// Where to send people whose confirmation link worked. We show them a page
+    /// of our own if not set.
+    pub confirm_success_url: Option<String>,
+    /// Where to send people whose confirmation link didn't work, with a `reason`
+    /// query parameter. We show them a page of our own if not set.
+    pub confirm_failure_url: Option<String>,
+    /// Accept subscriptions posted without a CSRF token, from forms hosted on other
+    /// sites. Our own form at `GET /subscribe` always sends one.
+    ///
+    /// Temporary, until the forms still posting to `/subscribe` move to the embed
+    /// or the JSON API. These posts skip CSRF protection, so it's off unless set.
+    #[serde(default)]
+    pub legacy_form_posts: bool,
+    /// Serve previews of our templates at `/templates/{name}/preview`. Anyone can
+    /// see them, so only for local development.
+    #[serde(default)]
+    pub template_previews: bool,
+    /// Which other sites may call the public subscription endpoints from browser
+    /// JavaScript. The widget works on any site without being listed here.
+    pub cors: CorsSettings,
+    /// Which addresses may subscribe
+    pub email_policy: EmailPolicySettings,
+    /// Which domains we suggest corrections for typos of
+    pub email_suggestions: EmailSuggestionSettings,
+    /// Subscription forms submitted quicker than this after being rendered are
+    /// taken for bots, and dropped
+    pub minimum_seconds_to_submit: u64,
+}
+
+/// Settings for cross-origin requests to the public subscription endpoints
+#[derive(Deserialize, Clone)]
+pub struct CorsSettings {
+    /// Origins like `https://example.com`, or `https://*.example.com` for all of
+    /// its subdomains
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers scripts may set, besides the ones browsers always allow
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache the answer to a preflight request
+    pub max_age_seconds: usize,
+}
+
+impl CorsSettings {
+    /// Parses and validates the policy
+    pub fn policy(&self) -> Result<CorsPolicy, CorsError> {
+        CorsPolicy::new(
+            &self.allowed_origins,
+            &self.allowed_methods,
+            &self.allowed_headers,
+            self.max_age_seconds,
+        )
+    }
+}
+
+/// Settings for which email addresses may subscribe
+#[derive(Deserialize, Clone)]
+pub struct EmailPolicySettings {
+    pub max_length: usize,
+    /// A file listing throwaway email providers, relative to the working directory
+    pub disposable_domains_file: Option<String>,
+    /// Only addresses at these domains may subscribe, if any are set
+    pub allowed_domains: Vec<String>,
+    /// Local parts of shared addresses, like `postmaster`
+    pub role_accounts: Vec<String>,
+}
+
+impl EmailPolicySettings {
+    /// Builds the policy, reading the disposable domains file.
+    pub fn policy(&self) -> std::io::Result<EmailPolicy> {
+        let disposable_domains = match &self.disposable_domains_file {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path).map_err(|err| {
+                    std::io::Error::new(err.kind(), format!("Failed to read {}: {}", path, err))
+                })?;
+                EmailPolicy::parse_domain_list(&contents)
+            }
+            None => Vec::new(),
+        };
+
+        Ok(EmailPolicy::new(
+            self.max_length,
+            &disposable_domains,
+            &self.allowed_domains,
+            &self.role_accounts,
+        ))
+    }
+}
+
+/// Settings for suggesting corrections of misspelled email domains
+#[derive(Deserialize, Clone)]
+pub struct EmailSuggestionSettings {
+    /// Domains of common email providers, preferred in this order
+    pub providers: Vec<String>,
+    /// How many typos away from a provider's domain a domain may be
+    pub max_distance: usize,
+}
+
+impl EmailSuggestionSettings {
+    pub fn suggester(&self) -> EmailSuggester {
+        EmailSuggester::new(&self.providers, self.max_distance)
+    }
+}
+
+impl ApplicationSettings {
+    /// Looks up the signing key from wherever it is configured to come from. The app
+    /// doesn't start without one.
+    pub fn hmac_secret(&self) -> Result<Secret<String>, SecretError> {
+        read_secret(
+            "the HMAC secret",
+            &self.hmac_secret,
+            &self.hmac_secret_env,
+            &self.hmac_secret_file,
+        )
+    }
+
+    pub fn bot_check(&self) -> BotCheck {
+        BotCheck {
+            minimum_time_to_submit: std::time::Duration::from_secs(self.minimum_seconds_to_submit),
+        }
+    }
+
+    /// Parses and validates the settings of the confirmation endpoint.
+    pub fn confirmation_options(&self) -> Result<ConfirmationOptions, url::ParseError> {
+        let parse = |url: &Option<String>| url.as_deref().map(url::Url::parse).transpose();
+
+        Ok(ConfirmationOptions {
+            token_lifetime: chrono::Duration::hours(self.subscription_token_lifetime_hours),
+            success_url: parse(&self.confirm_success_url)?,
+            failure_url: parse(&self.confirm_failure_url)?,
+        })
+    }
 }
 
 impl DatabaseSettings {
@@ -61,13 +222,208 @@ pub struct DatabaseSettings {
 /// sending of messages to subscribers.
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
-    pub base_url: String,
     pub sender_email: String,
-    pub authorization_token: Secret<String>,
+    /// Providers to send through, in order of preference. We fail over to the next
+    /// one while a provider is down.
+    pub providers: Vec<EmailProviderSettings>,
+    /// How many times to retry a send that failed for a transient reason
+    pub max_retries: u32,
+    /// Backoff before the first retry. Doubles with every retry after that.
+    pub retry_base_delay_milliseconds: u64,
+    /// Upper bound on the backoff between two retries
+    pub retry_max_delay_milliseconds: u64,
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub rate_limit: RateLimitSettings,
+    /// Signs outgoing messages when set. Only relevant for transports that hand
+    /// over complete MIME messages, API providers like Postmark sign for us.
+    pub dkim: Option<DkimSettings>,
+}
+
+/// A service that sends email on our behalf.
+///
+/// Its API token comes from exactly one of `authorization_token`,
+/// `authorization_token_env` or `authorization_token_file`. Deployments use the
+/// last two, so no token is ever committed: `APP__` variables can't reach into
+/// the `providers` list.
+#[derive(Deserialize, Clone)]
+pub struct EmailProviderSettings {
+    /// Identifies the provider in logs and in the health check
+    pub name: String,
+    pub base_url: String,
+    /// The token itself, for local development against a fake provider
+    pub authorization_token: Option<Secret<String>>,
+    /// The environment variable holding the token
+    pub authorization_token_env: Option<String>,
+    /// A file holding the token, like a mounted secret. Surrounding whitespace is
+    /// ignored.
+    pub authorization_token_file: Option<PathBuf>,
     pub timeout_milliseconds: u64,
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum EmailClientSettingsError {
+    #[error(transparent)]
+    Token(#[from] SecretError),
+    #[error(transparent)]
+    Dkim(#[from] DkimError),
+}
+
+/// Why a secret couldn't be looked up
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("{0} needs exactly one source")]
+    Source(String),
+    #[error("{name} is not set in {variable}")]
+    MissingVariable { name: String, variable: String },
+    #[error("Failed to read {name}")]
+    File {
+        name: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Looks up the secret called `name`, which is either set as is in `value`, or read
+/// from the environment `variable` or from `file`. Deployments use the last two, so
+/// secrets are never committed. Surrounding whitespace is ignored, and a variable
+/// that is empty counts as not set.
+fn read_secret(
+    name: &str,
+    value: &Option<Secret<String>>,
+    variable: &Option<String>,
+    file: &Option<PathBuf>,
+) -> Result<Secret<String>, SecretError> {
+    match (value, variable, file) {
+        (Some(value), None, None) => Ok(value.clone()),
+        (None, Some(variable), None) => std::env::var(variable)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| Secret::new(value.trim().to_string()))
+            .ok_or_else(|| SecretError::MissingVariable {
+                name: name.to_string(),
+                variable: variable.clone(),
+            }),
+        (None, None, Some(path)) => std::fs::read_to_string(path)
+            .map(|value| Secret::new(value.trim().to_string()))
+            .map_err(|source| SecretError::File {
+                name: name.to_string(),
+                source,
+            }),
+        _ => Err(SecretError::Source(name.to_string())),
+    }
+}
+
+impl EmailProviderSettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+
+    /// Looks up the API token from wherever it is configured to come from.
+    pub fn authorization_token(&self) -> Result<Secret<String>, SecretError> {
+        read_secret(
+            &format!("the authorization token of email provider {}", self.name),
+            &self.authorization_token,
+            &self.authorization_token_env,
+            &self.authorization_token_file,
+        )
+    }
+}
+
+/// The key we sign outgoing messages with, and where receivers find its public half
+/// in DNS.
+#[derive(Deserialize, Clone)]
+pub struct DkimSettings {
+    pub domain: String,
+    pub selector: String,
+    pub algorithm: DkimAlgorithm,
+    /// PEM encoded
+    pub private_key: Secret<String>,
+}
+
+impl DkimSettings {
+    pub fn signer(&self) -> Result<DkimSigner, DkimError> {
+        DkimSigner::new(
+            self.domain.clone(),
+            self.selector.clone(),
+            self.algorithm,
+            &self.private_key,
+        )
+    }
+}
+
+/// How fast we may send email.
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// Across all recipients. No limit if not set.
+    pub messages_per_second: Option<u32>,
+    /// Caps for individual recipient domains, on top of the global limit
+    #[serde(default)]
+    pub domains: Vec<DomainRateLimitSettings>,
+}
+
+/// How fast we may send email to addresses at `domain`.
+#[derive(Deserialize, Clone)]
+pub struct DomainRateLimitSettings {
+    pub domain: String,
+    pub messages_per_minute: u32,
+}
+
+/// When to stop calling the email provider because it seems to be down.
+#[derive(Deserialize, Clone)]
+pub struct CircuitBreakerSettings {
+    /// The share of failed requests, between 0 and 1, at which the breaker opens
+    pub failure_rate_threshold: f64,
+    /// Number of most recent requests the failure rate is computed over
+    pub window_size: usize,
+    /// Don't open before we have seen at least this many requests
+    pub minimum_requests: usize,
+    /// How long to fail fast before probing the provider again
+    pub open_duration_milliseconds: u64,
+}
+
 impl EmailClientSettings {
+    /// Builds an `EmailClient` from these settings.
+    ///
+    /// Every provider gets a circuit breaker of its own.
+    ///
+    /// Panics if there are no providers, or if a base URL or the sender address are
+    /// invalid. Fails on a provider token we can't find, or a DKIM key we can't sign
+    /// with, so these stop the app at startup rather than when sending email.
+    pub fn client(self) -> Result<EmailClient, EmailClientSettingsError> {
+        let dkim_signer = self.dkim.as_ref().map(DkimSettings::signer).transpose()?;
+        let sender_email = self.sender().expect("Invalid sender email address");
+        let retry_policy = self.retry_policy();
+        let circuit_breaker_policy = self.circuit_breaker_policy();
+        let rate_limiter = self.rate_limiter();
+        let providers = self
+            .providers
+            .into_iter()
+            .map(|provider| {
+                let base_url = url::Url::parse(&provider.base_url).expect("Invalid base URL");
+                let authorization_token = provider.authorization_token()?;
+                let timeout = provider.timeout();
+                let circuit_breaker =
+                    CircuitBreaker::new(provider.name.clone(), circuit_breaker_policy.clone());
+                Ok(EmailProvider::new(
+                    provider.name,
+                    base_url,
+                    authorization_token,
+                    timeout,
+                    circuit_breaker,
+                ))
+            })
+            .collect::<Result<_, EmailClientSettingsError>>()?;
+
+        let client = EmailClient::new(sender_email, providers, retry_policy, rate_limiter);
+        Ok(match dkim_signer {
+            Some(signer) => {
+                tracing::info!(dns_record = %signer.dns_record(), "Signing email with DKIM");
+                client.with_dkim_signer(signer)
+            }
+            None => client,
+        })
+    }
+
     /// Parses and validates the email address to use a sender.
     ///
     /// This will clone strings, so don't call it in a loop or anything.
@@ -75,8 +431,148 @@ impl EmailClientSettings {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
-    pub fn timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_millis(self.timeout_milliseconds)
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            base_delay: std::time::Duration::from_millis(self.retry_base_delay_milliseconds),
+            max_delay: std::time::Duration::from_millis(self.retry_max_delay_milliseconds),
+        }
+    }
+
+    pub fn rate_limiter(&self) -> SendRateLimiter {
+        let settings = &self.rate_limit;
+        let domains = settings
+            .domains
+            .iter()
+            .map(|domain| {
+                (
+                    domain.domain.clone(),
+                    RateLimit::per_minute(domain.messages_per_minute),
+                )
+            })
+            .collect();
+
+        SendRateLimiter::new(
+            settings.messages_per_second.map(RateLimit::per_second),
+            domains,
+        )
+    }
+
+    pub fn circuit_breaker_policy(&self) -> CircuitBreakerPolicy {
+        let settings = &self.circuit_breaker;
+        CircuitBreakerPolicy {
+            failure_rate_threshold: settings.failure_rate_threshold,
+            window_size: settings.window_size,
+            minimum_requests: settings.minimum_requests,
+            open_duration: std::time::Duration::from_millis(settings.open_duration_milliseconds),
+        }
+    }
+}
+
+/// Configuration for the endpoint Postmark calls to tell us about bounces and
+/// spam complaints.
+///
+/// Its password comes from exactly one of `password`, `password_env` or
+/// `password_file`. Anyone knowing it can suppress our subscribers, so deployments
+/// use the last two.
+#[derive(Deserialize, Clone)]
+pub struct PostmarkWebhookSettings {
+    /// Postmark sends these as HTTP basic auth credentials on every webhook call
+    pub username: String,
+    /// The password itself, for local development
+    pub password: Option<Secret<String>>,
+    /// The environment variable holding the password
+    pub password_env: Option<String>,
+    /// A file holding the password, like a mounted secret
+    pub password_file: Option<PathBuf>,
+    /// Number of soft bounces after which we stop sending to an address
+    pub soft_bounce_threshold: i32,
+}
+
+impl PostmarkWebhookSettings {
+    /// Looks up the password, the app doesn't start without one.
+    pub fn options(&self) -> Result<PostmarkWebhookOptions, SecretError> {
+        Ok(PostmarkWebhookOptions {
+            username: self.username.clone(),
+            password: read_secret(
+                "the Postmark webhook password",
+                &self.password,
+                &self.password_env,
+                &self.password_file,
+            )?,
+            soft_bounce_threshold: self.soft_bounce_threshold,
+        })
+    }
+}
+
+/// How often clients may call the endpoints that cost us an email, or that bots
+/// could guess tokens on.
+#[derive(Deserialize, Clone)]
+pub struct RequestLimitSettings {
+    pub store: RequestLimitStore,
+    /// How many proxies in front of us append the address they were called from
+    /// to `X-Forwarded-For`. Client IPs are taken from the entry the outermost one
+    /// added. Leave at 0 unless every request goes through them, clients can send
+    /// the header too.
+    pub trusted_proxies: usize,
+    pub subscribe: EndpointLimitSettings,
+    pub confirm: EndpointLimitSettings,
+}
+
+/// Where request counts are kept
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestLimitStore {
+    /// Each instance counts on its own
+    Memory,
+    /// Instances share their counts through the database
+    Postgres,
+}
+
+/// The limits of one endpoint. No limit for the scopes not set.
+#[derive(Deserialize, Clone)]
+pub struct EndpointLimitSettings {
+    pub per_ip: Option<LimitSettings>,
+    pub per_email: Option<LimitSettings>,
+    pub global: Option<LimitSettings>,
+}
+
+/// At most `requests` per `window_seconds`
+#[derive(Deserialize, Clone)]
+pub struct LimitSettings {
+    pub requests: u32,
+    pub window_seconds: u64,
+}
+
+impl EndpointLimitSettings {
+    fn limits(&self) -> EndpointLimits {
+        let limit = |entry: &Option<LimitSettings>| {
+            entry.as_ref().map(|entry| RequestLimit {
+                requests: entry.requests,
+                window: std::time::Duration::from_secs(entry.window_seconds),
+            })
+        };
+        EndpointLimits {
+            per_ip: limit(&self.per_ip),
+            per_email: limit(&self.per_email),
+            global: limit(&self.global),
+        }
+    }
+}
+
+impl RequestLimitSettings {
+    /// Builds the limiter, keeping counts in `pool` if so configured.
+    pub fn limiter(&self, pool: PgPool) -> RequestLimiter {
+        let store = match self.store {
+            RequestLimitStore::Memory => LimitStore::memory(),
+            RequestLimitStore::Postgres => LimitStore::Postgres(pool),
+        };
+        let limits = HashMap::from([
+            (LimitedEndpoint::Subscribe, self.subscribe.limits()),
+            (LimitedEndpoint::Confirm, self.confirm.limits()),
+        ]);
+
+        RequestLimiter::new(store, limits, self.trusted_proxies)
     }
 }
 
@@ -93,10 +589,26 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .try_into()
         .expect("Failed to parse APP_ENVIRONMENT");
 
+    load_configuration(&config_dir, &environment, app_variables())
+}
+
+/// `APP__` environment variables, e.g. `APP__APPLICATION__PORT` overrides
+/// `application.port`
+fn app_variables() -> config::Environment {
+    config::Environment::with_prefix("app").separator("__")
+}
+
+/// Loads the settings of `environment` from the YAML files in `config_dir`, with
+/// `variables` taking precedence.
+fn load_configuration(
+    config_dir: &std::path::Path,
+    environment: &Environment,
+    variables: config::Environment,
+) -> Result<Settings, config::ConfigError> {
     config::Config::builder()
         .add_source(config::File::from(config_dir.join("base")).required(true))
         .add_source(config::File::from(config_dir.join(environment.as_str())).required(true))
-        .add_source(config::Environment::with_prefix("app").separator("__"))
+        .add_source(variables)
         .build()?
         .try_deserialize()
 }
@@ -129,3 +641,197 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use secrecy::{ExposeSecret, Secret};
+    use uuid::Uuid;
+
+    use super::{
+        app_variables, load_configuration, DkimAlgorithm, DkimError, DkimSettings,
+        EmailClientSettingsError, EmailProviderSettings, Environment, SecretError,
+    };
+
+    /// The variables deployments set, see `spec.yaml`
+    fn deployment_variables() -> config::Environment {
+        let variables = [
+            ("APP__DATABASE__USERNAME", "newsletter"),
+            ("APP__DATABASE__PASSWORD", "password"),
+            ("APP__DATABASE__HOST", "db.example.com"),
+            ("APP__DATABASE__PORT", "25060"),
+            ("APP__DATABASE__DATABASE_NAME", "newsletter"),
+            (
+                "APP__APPLICATION__BASE_URL",
+                "https://newsletter.example.com",
+            ),
+        ];
+        let variables = variables
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        app_variables().source(Some(variables))
+    }
+
+    fn provider(
+        token: Option<&str>,
+        variable: Option<&str>,
+        file: Option<&Path>,
+    ) -> EmailProviderSettings {
+        EmailProviderSettings {
+            name: "postmark".to_string(),
+            base_url: "https://api.postmarkapp.com".to_string(),
+            authorization_token: token.map(|token| Secret::new(token.to_string())),
+            authorization_token_env: variable.map(str::to_string),
+            authorization_token_file: file.map(Path::to_path_buf),
+            timeout_milliseconds: 10000,
+        }
+    }
+
+    #[test]
+    fn provider_tokens_come_from_the_environment_or_a_file() {
+        let variable = format!("TEST_PROVIDER_TOKEN_{}", Uuid::new_v4().simple());
+        std::env::set_var(&variable, "token-from-env");
+        let file = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::write(&file, "token-from-file\n").unwrap();
+
+        let from_env = provider(None, Some(&variable), None).authorization_token();
+        let from_file = provider(None, None, Some(&file)).authorization_token();
+
+        assert_eq!(from_env.unwrap().expose_secret(), "token-from-env");
+        assert_eq!(from_file.unwrap().expose_secret(), "token-from-file");
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn provider_tokens_must_be_found_in_exactly_one_place() {
+        let unset = format!("TEST_PROVIDER_TOKEN_{}", Uuid::new_v4().simple());
+        let missing_file = std::env::temp_dir().join(Uuid::new_v4().to_string());
+
+        assert!(matches!(
+            provider(None, Some(&unset), None).authorization_token(),
+            Err(SecretError::MissingVariable { .. })
+        ));
+        assert!(matches!(
+            provider(None, None, Some(&missing_file)).authorization_token(),
+            Err(SecretError::File { .. })
+        ));
+        for settings in [
+            provider(None, None, None),
+            provider(Some("token"), Some(&unset), None),
+        ] {
+            assert!(matches!(
+                settings.authorization_token(),
+                Err(SecretError::Source(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_dkim_keys_fail_building_the_email_client() {
+        let config_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+        let mut settings =
+            load_configuration(&config_dir, &Environment::Local, deployment_variables()).unwrap();
+        settings.email_client.dkim = Some(DkimSettings {
+            domain: "example.com".to_string(),
+            selector: "mail".to_string(),
+            algorithm: DkimAlgorithm::RsaSha256,
+            private_key: Secret::new("not a key".to_string()),
+        });
+
+        assert!(matches!(
+            settings.email_client.client(),
+            Err(EmailClientSettingsError::Dkim(DkimError::InvalidKey(_)))
+        ));
+    }
+
+    #[test]
+    fn posts_without_a_csrf_token_are_refused_in_production() {
+        let config_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+
+        let settings = load_configuration(
+            &config_dir,
+            &Environment::Production,
+            deployment_variables(),
+        )
+        .unwrap();
+
+        assert!(!settings.application.legacy_form_posts);
+    }
+
+    #[test]
+    fn production_does_not_start_without_a_signing_key() {
+        let config_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+
+        let settings = load_configuration(
+            &config_dir,
+            &Environment::Production,
+            deployment_variables(),
+        )
+        .unwrap();
+
+        // Tests don't set HMAC_SECRET, like a deployment that forgot to
+        assert!(matches!(
+            settings.application.hmac_secret(),
+            Err(SecretError::MissingVariable { variable, .. }) if variable == "HMAC_SECRET"
+        ));
+    }
+
+    #[test]
+    fn production_does_not_start_without_a_webhook_password() {
+        let config_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+
+        let settings = load_configuration(
+            &config_dir,
+            &Environment::Production,
+            deployment_variables(),
+        )
+        .unwrap();
+
+        // Tests don't set POSTMARK_WEBHOOK_PASSWORD, like a deployment that forgot to
+        assert!(matches!(
+            settings.postmark_webhook.options(),
+            Err(SecretError::MissingVariable { variable, .. })
+                if variable == "POSTMARK_WEBHOOK_PASSWORD"
+        ));
+    }
+
+    #[test]
+    fn template_previews_are_not_served_in_production() {
+        let config_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+
+        let settings = load_configuration(
+            &config_dir,
+            &Environment::Production,
+            deployment_variables(),
+        )
+        .unwrap();
+
+        assert!(!settings.application.template_previews);
+    }
+
+    #[test]
+    fn every_configuration_file_loads() {
+        let config_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+        let files = std::fs::read_dir(&config_dir).expect("Failed to list config files");
+
+        for file in files {
+            let path = file.unwrap().path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("yaml") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+            if name == "base" {
+                continue;
+            }
+
+            let environment = Environment::try_from(name.clone())
+                .unwrap_or_else(|err| panic!("{} is not for an environment: {}", name, err));
+            if let Err(err) = load_configuration(&config_dir, &environment, deployment_variables())
+            {
+                panic!("Failed to load {}: {}", path.display(), err);
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
+use url::Url;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CorsError {
+    #[error(
+        "{0} is not a valid origin, expected e.g. https://example.com or https://*.example.com"
+    )]
+    InvalidOrigin(String),
+    #[error("{0} is not a valid HTTP method")]
+    InvalidMethod(String),
+    #[error("{0} is not a valid header name")]
+    InvalidHeader(String),
+}
+
+/// An origin allowed to call us from browser JavaScript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginPattern {
+    /// Exactly this origin, e.g. `https://example.com`
+    Exact(String),
+    /// Any subdomain of `domain`, at any depth, but not `domain` itself. Written
+    /// `https://*.example.com`.
+    Subdomains {
+        scheme: String,
+        domain: String,
+        port: Option<u16>,
+    },
+}
+
+impl OriginPattern {
+    pub fn parse(pattern: &str) -> Result<Self, CorsError> {
+        let invalid = || CorsError::InvalidOrigin(pattern.to_string());
+        let (scheme, host) = pattern.split_once("://").ok_or_else(invalid)?;
+        let (wildcard, origin) = match host.strip_prefix("*.") {
+            Some(domain) => (true, format!("{}://{}", scheme, domain)),
+            None => (false, pattern.to_string()),
+        };
+        if origin.contains('*') {
+            return Err(invalid());
+        }
+        let url = parse_origin(&origin).ok_or_else(invalid)?;
+
+        if wildcard {
+            Ok(Self::Subdomains {
+                scheme: url.scheme().to_string(),
+                domain: url.host_str().ok_or_else(invalid)?.to_string(),
+                port: url.port(),
+            })
+        } else {
+            Ok(Self::Exact(url.origin().ascii_serialization()))
+        }
+    }
+
+    /// Whether browsers at `origin`, the value of an `Origin` header, may call us.
+    pub fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Exact(allowed) => parse_origin(origin)
+                .is_some_and(|url| url.origin().ascii_serialization() == *allowed),
+            Self::Subdomains {
+                scheme,
+                domain,
+                port,
+            } => {
+                let url = match parse_origin(origin) {
+                    Some(url) => url,
+                    None => return false,
+                };
+                let is_subdomain = url
+                    .host_str()
+                    .and_then(|host| host.strip_suffix(domain.as_str()))
+                    .is_some_and(|label| label.len() > 1 && label.ends_with('.'));
+
+                url.scheme() == scheme && url.port() == *port && is_subdomain
+            }
+        }
+    }
+}
+
+/// Parses `origin` if it is one: a scheme, host and optional port, no path.
+fn parse_origin(origin: &str) -> Option<Url> {
+    let url = Url::parse(origin).ok()?;
+    if url.host().is_none() || url.path() != "/" || url.query().is_some() {
+        return None;
+    }
+    Some(url)
+}
+
+/// Which browser JavaScript, from other sites, may call our public endpoints.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    origins: Arc<Vec<OriginPattern>>,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    max_age_seconds: usize,
+}
+
+impl CorsPolicy {
+    pub fn new(
+        origins: &[String],
+        methods: &[String],
+        headers: &[String],
+        max_age_seconds: usize,
+    ) -> Result<Self, CorsError> {
+        let origins = origins
+            .iter()
+            .map(|origin| OriginPattern::parse(origin))
+            .collect::<Result<_, _>>()?;
+        let methods = methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .map_err(|_| CorsError::InvalidMethod(method.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+        let headers = headers
+            .iter()
+            .map(|header| {
+                HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|_| CorsError::InvalidHeader(header.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            origins: Arc::new(origins),
+            methods,
+            headers,
+            max_age_seconds,
+        })
+    }
+
+    /// Whether browsers at `origin` may call us.
+    pub fn allows(&self, origin: &str) -> bool {
+        self.origins.iter().any(|pattern| pattern.matches(origin))
+    }
+
+    /// The middleware applying the policy. Requests from other origins are still
+    /// handled, they just don't get CORS headers, so browsers won't let scripts
+    /// read the response.
+    pub fn middleware(&self) -> Cors {
+        let policy = self.clone();
+        Cors::default()
+            .allowed_origin_fn(move |origin, _| {
+                origin
+                    .to_str()
+                    .map(|origin| policy.allows(origin))
+                    .unwrap_or(false)
+            })
+            .allowed_methods(self.methods.clone())
+            .allowed_headers(self.headers.clone())
+            .max_age(self.max_age_seconds)
+    }
+}
+
+/// The middleware for endpoints scripts on any page may call, like the one the
+/// widget posts to. Responses never depend on cookies or other credentials, so
+/// they are shared with every origin.
+pub fn any_origin_middleware() -> Cors {
+    Cors::default()
+        .allow_any_origin()
+        .send_wildcard()
+        .allowed_methods([Method::POST])
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+
+    use super::{CorsPolicy, OriginPattern};
+
+    fn policy(origins: &[&str]) -> CorsPolicy {
+        let origins: Vec<String> = origins.iter().map(|origin| origin.to_string()).collect();
+        CorsPolicy::new(&origins, &["POST".to_string()], &[], 600).unwrap()
+    }
+
+    #[test]
+    fn exact_origins_match_only_themselves() {
+        let policy = policy(&["https://partner.example"]);
+
+        assert!(policy.allows("https://partner.example"));
+        assert!(policy.allows("https://PARTNER.example"));
+        assert!(!policy.allows("http://partner.example"));
+        assert!(!policy.allows("https://partner.example:8443"));
+        assert!(!policy.allows("https://www.partner.example"));
+        assert!(!policy.allows("https://partner.example.evil.com"));
+    }
+
+    #[test]
+    fn wildcards_match_subdomains_only() {
+        let policy = policy(&["https://*.partner.example"]);
+
+        assert!(policy.allows("https://www.partner.example"));
+        assert!(policy.allows("https://a.b.partner.example"));
+        assert!(!policy.allows("https://partner.example"));
+        assert!(!policy.allows("https://evilpartner.example"));
+        assert!(!policy.allows("https://www.partner.example.evil.com"));
+        assert!(!policy.allows("http://www.partner.example"));
+    }
+
+    #[test]
+    fn ports_must_match() {
+        let policy = policy(&["http://localhost:3000", "https://*.partner.example:8443"]);
+
+        assert!(policy.allows("http://localhost:3000"));
+        assert!(!policy.allows("http://localhost:3001"));
+        assert!(policy.allows("https://www.partner.example:8443"));
+        assert!(!policy.allows("https://www.partner.example"));
+    }
+
+    #[test]
+    fn nothing_is_allowed_by_default() {
+        assert!(!policy(&[]).allows("https://partner.example"));
+        assert!(!policy(&["https://partner.example"]).allows("null"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        for pattern in [
+            "partner.example",
+            "https://partner.example/path",
+            "https://*",
+            "https://www.*.partner.example",
+            "https://*partner.example",
+        ] {
+            assert_err!(OriginPattern::parse(pattern), "{}", pattern);
+        }
+        assert_err!(CorsPolicy::new(&[], &["NOT A METHOD".to_string()], &[], 0));
+        assert_err!(CorsPolicy::new(&[], &[], &["bad header".to_string()], 0));
+    }
+}
@@ -0,0 +1,176 @@
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    HttpRequest,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::signing::{self, Purpose, SigningError};
+
+/// The cookie holding the signed CSRF token
+pub const CSRF_COOKIE: &str = "_csrf";
+
+/// How long a form can sit in a browser before it has to be reloaded
+pub(crate) const TOKEN_LIFETIME_HOURS: i64 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CsrfError {
+    #[error("There is no CSRF cookie")]
+    MissingCookie,
+    #[error("The CSRF cookie is invalid")]
+    InvalidCookie(#[source] SigningError),
+    #[error("The CSRF token has expired")]
+    Expired,
+    #[error("The submitted CSRF token doesn't match the cookie")]
+    Mismatch,
+}
+
+/// Protects our forms against cross-site request forgery, with the double submit
+/// pattern: the token goes into a hidden field of the form and, signed, into a
+/// cookie. Other sites can make browsers post to us, cookie included, but they
+/// can't read the cookie to put its token in the form.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsrfToken {
+    token: String,
+    issued_at: DateTime<Utc>,
+}
+
+impl CsrfToken {
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect();
+
+        Self {
+            token,
+            issued_at: Utc::now(),
+        }
+    }
+
+    /// The value for the hidden form field
+    pub fn as_str(&self) -> &str {
+        &self.token
+    }
+
+    /// The token in the cookie of `request`, if it is still valid.
+    pub fn from_request(request: &HttpRequest, secret: &Secret<String>) -> Result<Self, CsrfError> {
+        Self::from_request_at(request, secret, Utc::now())
+    }
+
+    fn from_request_at(
+        request: &HttpRequest,
+        secret: &Secret<String>,
+        now: DateTime<Utc>,
+    ) -> Result<Self, CsrfError> {
+        let cookie = request
+            .cookie(CSRF_COOKIE)
+            .ok_or(CsrfError::MissingCookie)?;
+        let token: Self = signing::verify(Purpose::Csrf, cookie.value(), secret)
+            .map_err(CsrfError::InvalidCookie)?;
+        if now - token.issued_at > Duration::hours(TOKEN_LIFETIME_HOURS) {
+            return Err(CsrfError::Expired);
+        }
+
+        Ok(token)
+    }
+
+    /// Checks that `submitted`, from the hidden form field, matches the token in the
+    /// cookie of `request`.
+    pub fn verify(
+        request: &HttpRequest,
+        submitted: &str,
+        secret: &Secret<String>,
+    ) -> Result<(), CsrfError> {
+        let token = Self::from_request(request, secret)?;
+        if token.token != submitted {
+            return Err(CsrfError::Mismatch);
+        }
+
+        Ok(())
+    }
+
+    /// The cookie to send along with the form. Browsers only send it back when
+    /// the form is submitted from our own pages. `secure` keeps it off plain HTTP.
+    pub fn cookie(&self, secret: &Secret<String>, secure: bool) -> Cookie<'static> {
+        Cookie::build(CSRF_COOKIE, signing::sign(Purpose::Csrf, self, secret))
+            .path("/")
+            .http_only(true)
+            .secure(secure)
+            .same_site(SameSite::Strict)
+            .max_age(time::Duration::hours(TOKEN_LIFETIME_HOURS))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{CsrfError, CsrfToken};
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn the_token_in_the_cookie_is_accepted() {
+        let token = CsrfToken::generate();
+        let request = TestRequest::default()
+            .cookie(token.cookie(&secret(), true))
+            .to_http_request();
+
+        assert_ok!(CsrfToken::verify(&request, token.as_str(), &secret()));
+        assert_eq!(CsrfToken::from_request(&request, &secret()).unwrap(), token);
+    }
+
+    #[test]
+    fn another_token_is_rejected() {
+        let request = TestRequest::default()
+            .cookie(CsrfToken::generate().cookie(&secret(), true))
+            .to_http_request();
+
+        let result = CsrfToken::verify(&request, CsrfToken::generate().as_str(), &secret());
+
+        assert!(matches!(result, Err(CsrfError::Mismatch)));
+    }
+
+    #[test]
+    fn a_token_without_its_cookie_is_rejected() {
+        let request = TestRequest::default().to_http_request();
+
+        let result = CsrfToken::verify(&request, CsrfToken::generate().as_str(), &secret());
+
+        assert!(matches!(result, Err(CsrfError::MissingCookie)));
+    }
+
+    #[test]
+    fn a_cookie_signed_with_another_secret_is_rejected() {
+        let token = CsrfToken::generate();
+        let other_secret = Secret::new("another-key".to_string());
+        let request = TestRequest::default()
+            .cookie(token.cookie(&other_secret, true))
+            .to_http_request();
+
+        assert_err!(CsrfToken::verify(&request, token.as_str(), &secret()));
+    }
+
+    #[test]
+    fn old_tokens_expire() {
+        let token = CsrfToken::generate();
+        let request = TestRequest::default()
+            .cookie(token.cookie(&secret(), true))
+            .to_http_request();
+
+        let result =
+            CsrfToken::from_request_at(&request, &secret(), Utc::now() + Duration::hours(3));
+
+        assert!(matches!(result, Err(CsrfError::Expired)));
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashSet;
+
+use super::SubscriberEmail;
+
+/// Why an otherwise valid address isn't accepted for signup.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EmailRejection {
+    #[error("Email addresses can be at most {max_length} characters long.")]
+    TooLong { max_length: usize },
+    #[error("{0} only hands out throwaway addresses, please use another one.")]
+    DisposableDomain(String),
+    #[error("Only addresses at selected domains can subscribe, {0} isn't one of them.")]
+    DomainNotAllowed(String),
+    #[error("{0}@ addresses are shared by a team, please use a personal address.")]
+    RoleAccount(String),
+}
+
+impl EmailRejection {
+    /// A stable code for the reason, for API clients
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TooLong { .. } => "too_long",
+            Self::DisposableDomain(_) => "disposable_domain",
+            Self::DomainNotAllowed(_) => "domain_not_allowed",
+            Self::RoleAccount(_) => "role_account",
+        }
+    }
+}
+
+/// Which valid addresses may sign up.
+///
+/// Domains match their subdomains too, so blocking `example.com` also blocks
+/// `mail.example.com`.
+#[derive(Debug, Clone)]
+pub struct EmailPolicy {
+    max_length: usize,
+    disposable_domains: HashSet<String>,
+    /// Everything is allowed when empty
+    allowed_domains: HashSet<String>,
+    /// Local parts like `postmaster`, compared without any `+tag`
+    role_accounts: HashSet<String>,
+}
+
+impl EmailPolicy {
+    pub fn new(
+        max_length: usize,
+        disposable_domains: &[String],
+        allowed_domains: &[String],
+        role_accounts: &[String],
+    ) -> Self {
+        let normalize = |values: &[String]| {
+            values
+                .iter()
+                .map(|value| value.trim().trim_end_matches('.').to_lowercase())
+                .filter(|value| !value.is_empty())
+                .collect()
+        };
+
+        Self {
+            max_length,
+            disposable_domains: normalize(disposable_domains),
+            allowed_domains: normalize(allowed_domains),
+            role_accounts: normalize(role_accounts),
+        }
+    }
+
+    /// Reads a domain list file: one domain per line, `#` starts a comment.
+    pub fn parse_domain_list(contents: &str) -> Vec<String> {
+        contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Checks whether `email` may sign up, and why not.
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), EmailRejection> {
+        if email.as_ref().chars().count() > self.max_length {
+            return Err(EmailRejection::TooLong {
+                max_length: self.max_length,
+            });
+        }
+
+        let domain = email.domain().trim_end_matches('.').to_lowercase();
+        if !self.allowed_domains.is_empty() && !matches_any(&domain, &self.allowed_domains) {
+            return Err(EmailRejection::DomainNotAllowed(domain));
+        }
+        if matches_any(&domain, &self.disposable_domains) {
+            return Err(EmailRejection::DisposableDomain(domain));
+        }
+
+        let local_part = email
+            .as_ref()
+            .rsplit_once('@')
+            .map_or("", |(local_part, _)| local_part);
+        let account = local_part
+            .split('+')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if self.role_accounts.contains(&account) {
+            return Err(EmailRejection::RoleAccount(account));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `domain`, or one of the domains it is a subdomain of, is in `domains`
+fn matches_any(domain: &str, domains: &HashSet<String>) -> bool {
+    let mut candidate = domain;
+    loop {
+        if domains.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) => candidate = parent,
+            None => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_ok;
+
+    use super::{EmailPolicy, EmailRejection};
+    use crate::domain::SubscriberEmail;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn policy() -> EmailPolicy {
+        EmailPolicy::new(
+            40,
+            &strings(&["mailinator.com"]),
+            &[],
+            &strings(&["postmaster", "abuse"]),
+        )
+    }
+
+    fn check(policy: &EmailPolicy, email: &str) -> Result<(), EmailRejection> {
+        policy.check(&SubscriberEmail::parse(email.to_string()).unwrap())
+    }
+
+    #[test]
+    fn personal_addresses_are_accepted() {
+        assert_ok!(check(&policy(), "ursula@example.com"));
+        assert_ok!(check(&policy(), "postmaster.general@example.com"));
+    }
+
+    #[test]
+    fn long_addresses_are_rejected() {
+        let email = format!("{}@example.com", "u".repeat(30));
+
+        assert_eq!(
+            check(&policy(), &email),
+            Err(EmailRejection::TooLong { max_length: 40 })
+        );
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        for email in ["ursula@mailinator.com", "ursula@eu.Mailinator.COM"] {
+            assert!(
+                matches!(
+                    check(&policy(), email),
+                    Err(EmailRejection::DisposableDomain(_))
+                ),
+                "{}",
+                email
+            );
+        }
+        assert_ok!(check(&policy(), "ursula@notmailinator.com"));
+    }
+
+    #[test]
+    fn role_accounts_are_rejected_with_any_tag() {
+        for email in ["postmaster@example.com", "Abuse+reports@example.com"] {
+            assert!(
+                matches!(check(&policy(), email), Err(EmailRejection::RoleAccount(_))),
+                "{}",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn allowlists_only_let_their_domains_in() {
+        let policy = EmailPolicy::new(254, &[], &strings(&["example.com"]), &[]);
+
+        assert_ok!(check(&policy, "ursula@example.com"));
+        assert_ok!(check(&policy, "ursula@eng.example.com"));
+        assert_eq!(
+            check(&policy, "ursula@gmail.com"),
+            Err(EmailRejection::DomainNotAllowed("gmail.com".to_string()))
+        );
+    }
+
+    #[test]
+    fn domain_lists_skip_comments_and_blank_lines() {
+        let contents = "# Throwaway providers\nmailinator.com\n\n  yopmail.com # and .fr\n";
+
+        assert_eq!(
+            EmailPolicy::parse_domain_list(contents),
+            strings(&["mailinator.com", "yopmail.com"])
+        );
+    }
+}
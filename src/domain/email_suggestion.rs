@@ -0,0 +1,137 @@
+use super::SubscriberEmail;
+
+/// Suggests corrections for addresses at misspelled domains of common providers,
+/// like `gmial.com`.
+#[derive(Debug, Clone)]
+pub struct EmailSuggester {
+    /// Lowercased provider domains, in order of preference
+    providers: Vec<String>,
+    /// How many edits away from a provider's domain a domain may be to get a
+    /// suggestion
+    max_distance: usize,
+}
+
+impl EmailSuggester {
+    pub fn new(providers: &[String], max_distance: usize) -> Self {
+        Self {
+            providers: providers
+                .iter()
+                .map(|provider| provider.trim().to_lowercase())
+                .collect(),
+            max_distance,
+        }
+    }
+
+    /// The address `email` was probably meant to be, if its domain looks like a
+    /// typo of a provider's domain. Ties go to the provider listed first.
+    pub fn suggest(&self, email: &SubscriberEmail) -> Option<SubscriberEmail> {
+        let domain = email.domain();
+        if self.providers.iter().any(|provider| provider == domain) {
+            return None;
+        }
+
+        let (provider, _) = self
+            .providers
+            .iter()
+            .map(|provider| (provider, edit_distance(domain, provider)))
+            .filter(|(_, distance)| *distance <= self.max_distance)
+            .min_by_key(|(_, distance)| *distance)?;
+        SubscriberEmail::parse(format!("{}@{}", email.local_part(), provider)).ok()
+    }
+}
+
+/// The number of single character insertions, deletions, substitutions and
+/// transpositions of neighbours it takes to turn `a` into `b`. Swapped letters
+/// are the most common typo, so they count as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first i chars of `a` and the
+    // first j chars of `b`
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    distances[0] = (0..=b.len()).collect();
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, EmailSuggester};
+    use crate::domain::SubscriberEmail;
+
+    fn suggester() -> EmailSuggester {
+        let providers = ["gmail.com", "hotmail.com", "yahoo.com", "mail.com"]
+            .map(String::from)
+            .to_vec();
+        EmailSuggester::new(&providers, 2)
+    }
+
+    fn suggest(email: &str) -> Option<String> {
+        suggester()
+            .suggest(&SubscriberEmail::parse(email.to_string()).unwrap())
+            .map(|suggestion| suggestion.as_ref().to_string())
+    }
+
+    #[test]
+    fn edit_distance_counts_swapped_letters_once() {
+        assert_eq!(edit_distance("gmail.com", "gmail.com"), 0);
+        assert_eq!(edit_distance("gmial.com", "gmail.com"), 1);
+        assert_eq!(edit_distance("gmai.com", "gmail.com"), 1);
+        assert_eq!(edit_distance("gnail.con", "gmail.com"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn typos_of_providers_get_a_suggestion() {
+        assert_eq!(
+            suggest("Ursula@gmial.com").as_deref(),
+            Some("Ursula@gmail.com")
+        );
+        assert_eq!(
+            suggest("ursula@hotmial.com").as_deref(),
+            Some("ursula@hotmail.com")
+        );
+        assert_eq!(
+            suggest("ursula@yaho.com").as_deref(),
+            Some("ursula@yahoo.com")
+        );
+    }
+
+    #[test]
+    fn providers_and_unrelated_domains_get_none() {
+        for email in [
+            "ursula@gmail.com",
+            "ursula@mail.com",
+            "ursula@example.com",
+            "ursula@gmail.example.org",
+        ] {
+            assert_eq!(suggest(email), None, "{}", email);
+        }
+    }
+
+    #[test]
+    fn the_closest_provider_is_suggested() {
+        let providers = ["mail.com", "gmail.com"].map(String::from).to_vec();
+        let suggester = EmailSuggester::new(&providers, 2);
+        let email = SubscriberEmail::parse("ursula@gmaill.com".to_string()).unwrap();
+
+        let suggestion = suggester.suggest(&email).unwrap();
+
+        assert_eq!(suggestion.as_ref(), "ursula@gmail.com");
+    }
+}
@@ -0,0 +1,120 @@
+/// A language we have translations for. Stored in the `locale` column of
+/// `subscriptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    /// What we fall back to when there is no translation
+    #[default]
+    English,
+    French,
+    German,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 3] = [Self::English, Self::French, Self::German];
+
+    /// The language tag, e.g. `fr`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::French => "fr",
+            Self::German => "de",
+        }
+    }
+
+    /// Parses a language tag like `fr` or `fr-CA`. Only the language matters, the
+    /// region is ignored.
+    pub fn parse(tag: &str) -> Result<Self, String> {
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+        Self::ALL
+            .into_iter()
+            .find(|locale| locale.as_str().eq_ignore_ascii_case(language.trim()))
+            .ok_or_else(|| format!("{} is not a supported locale.", tag))
+    }
+
+    /// The supported locale an `Accept-Language` header value prefers most, if any.
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut preferences: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                    .map(|quality| quality.trim().parse().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // Stable, so equally preferred languages keep their order
+        preferences.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        preferences
+            .into_iter()
+            .find_map(|(tag, _)| Self::parse(tag).ok())
+    }
+
+    /// The locale for a new subscriber: the one they picked, if we support it, or
+    /// else the one their browser prefers, or else English.
+    pub fn negotiate(picked: Option<&str>, accept_language: Option<&str>) -> Self {
+        picked
+            .and_then(|tag| Self::parse(tag).ok())
+            .or_else(|| accept_language.and_then(Self::from_accept_language))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+
+    use super::Locale;
+
+    #[test]
+    fn locales_round_trip_through_their_tags() {
+        for locale in Locale::ALL {
+            assert_eq!(Locale::parse(locale.as_str()), Ok(locale));
+        }
+    }
+
+    #[test]
+    fn regions_and_case_are_ignored() {
+        assert_eq!(Locale::parse("fr-CA"), Ok(Locale::French));
+        assert_eq!(Locale::parse("DE_at"), Ok(Locale::German));
+    }
+
+    #[test]
+    fn unsupported_locales_are_rejected() {
+        assert_err!(Locale::parse("es"));
+        assert_err!(Locale::parse(""));
+    }
+
+    #[test]
+    fn the_most_preferred_supported_language_wins() {
+        let header = "es-ES;q=0.9, de;q=0.7, fr-FR;q=0.8, *;q=0.5";
+
+        assert_eq!(Locale::from_accept_language(header), Some(Locale::French));
+    }
+
+    #[test]
+    fn languages_without_a_quality_are_preferred_most() {
+        assert_eq!(
+            Locale::from_accept_language("en;q=0.5, de"),
+            Some(Locale::German)
+        );
+    }
+
+    #[test]
+    fn unacceptable_languages_are_skipped() {
+        assert_eq!(Locale::from_accept_language("fr;q=0, es"), None);
+        assert_eq!(Locale::from_accept_language("fr;q=oops"), None);
+    }
+
+    #[test]
+    fn a_picked_locale_beats_the_browser_preference() {
+        assert_eq!(Locale::negotiate(Some("de"), Some("fr")), Locale::German);
+        assert_eq!(Locale::negotiate(Some("es"), Some("fr")), Locale::French);
+        assert_eq!(Locale::negotiate(None, Some("es")), Locale::English);
+        assert_eq!(Locale::negotiate(None, None), Locale::English);
+    }
+}
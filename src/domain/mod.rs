@@ -1,7 +1,17 @@
+mod email_policy;
+mod email_suggestion;
+mod locale;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_source;
+mod suppression_reason;
 
+pub use email_policy::{EmailPolicy, EmailRejection};
+pub use email_suggestion::EmailSuggester;
+pub use locale::Locale;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_source::SubscriptionSource;
+pub use suppression_reason::SuppressionReason;
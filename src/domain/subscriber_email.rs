@@ -1,8 +1,36 @@
+use unicode_normalization::UnicodeNormalization;
+use url::Host;
 use validator::validate_email;
 
+use super::subscriber_name::is_invisible;
+
+/// Providers ignoring anything after a `+` in the local part, so `ursula+news@`
+/// gets the mail of `ursula@`. The migration backfilling `canonical_email` has
+/// a copy of this list.
+const PLUS_ADDRESSING_DOMAINS: &[&str] = &[
+    "fastmail.com",
+    "fastmail.fm",
+    "gmail.com",
+    "hotmail.com",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "pm.me",
+    "proton.me",
+    "protonmail.ch",
+    "protonmail.com",
+];
+
 /// An email address to send the newsletter to. Enforces validity of the email
 /// address, so any instance of this is guaranteed to have a valid email address.
 ///
+/// The domain is normalized, lowercased and in its ASCII (punycode) form, but the
+/// local part is kept as it was typed. Local parts beyond ASCII (RFC 6531) are
+/// accepted, NFC normalized.
+///
 /// # Examples
 /// Use the `parse` function to build a `SubscriberEmail` from a string.
 /// We can then get the email address back out using the `AsRef<str>` implementation.
@@ -12,21 +40,83 @@ use validator::validate_email;
 /// let name = SubscriberEmail::parse("valid@domain.com".to_string()).unwrap();
 /// assert_eq!("valid@domain.com", name.as_ref());
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
     /// Return `Ok` with a valid `SubscriberEmail` when `s` is a valid email address.
     /// Otherwise, returns `Err` with an error message describing the problem.
     pub fn parse(s: String) -> Result<Self, String> {
-        if validate_email(&s) {
-            Ok(SubscriberEmail(s))
+        let invalid = || format!("{} is not a valid subscriber email.", s);
+        let (local_part, domain) = s.rsplit_once('@').ok_or_else(invalid)?;
+        let domain = match Host::parse(domain) {
+            Ok(Host::Domain(domain)) => domain,
+            // IP address literals like `[127.0.0.1]`
+            _ => domain.to_lowercase(),
+        };
+
+        // `validate_email` only knows ASCII local parts, so internationalized ones
+        // are checked apart from their domain
+        let is_valid = if local_part.is_ascii() {
+            validate_email(&s)
         } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+            is_utf8_local_part(local_part) && validate_email(format!("user@{}", domain))
+        };
+        if !is_valid {
+            return Err(invalid());
         }
+
+        let local_part: String = local_part.nfc().collect();
+        Ok(SubscriberEmail(format!("{}@{}", local_part, domain)))
+    }
+
+    /// The part of the address before the `@`.
+    pub fn local_part(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map_or("", |(local_part, _)| local_part)
+    }
+
+    /// The part of the address after the `@`.
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
+
+    /// The address with what doesn't change where mail ends up folded away: case,
+    /// and for the providers known to ignore them, `+tags` and Gmail's dots. Two
+    /// spellings of the same mailbox have the same canonical form.
+    pub fn canonical(&self) -> String {
+        let domain = match self.domain() {
+            "googlemail.com" => "gmail.com",
+            domain => domain,
+        };
+        let mut local_part = self.local_part().to_lowercase();
+        if PLUS_ADDRESSING_DOMAINS.contains(&domain) {
+            local_part.truncate(local_part.find('+').unwrap_or(local_part.len()));
+        }
+        if domain == "gmail.com" {
+            local_part.retain(|c| c != '.');
+        }
+
+        format!("{}@{}", local_part, domain)
     }
 }
 
+/// Whether `local_part` is a dot-atom of at most 64 bytes, of ASCII `atext` and
+/// visible characters beyond ASCII, as RFC 6531 allows.
+fn is_utf8_local_part(local_part: &str) -> bool {
+    let is_atext = |c: char| {
+        c.is_ascii_alphanumeric()
+            || "!#$%&'*+-/=?^_`{|}~".contains(c)
+            || (!c.is_ascii() && !c.is_whitespace() && !is_invisible(c))
+    };
+
+    local_part.len() <= 64
+        && local_part
+            .split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
         &self.0
@@ -37,6 +127,95 @@ impl AsRef<str> for SubscriberEmail {
 mod tests {
     use super::SubscriberEmail;
     use claim::{assert_err, assert_ok};
+    use fake::{faker::internet::en::SafeEmail, Fake};
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[derive(Debug, Clone)]
+    struct ValidEmailFixture(String);
+
+    impl Arbitrary for ValidEmailFixture {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let mut rng = StdRng::seed_from_u64(u64::arbitrary(g));
+            Self(SafeEmail().fake_with_rng(&mut rng))
+        }
+    }
+
+    /// A local part of letters beyond ASCII, like `用户` or `пользователь`
+    #[derive(Debug, Clone)]
+    struct InternationalLocalPart(String);
+
+    impl Arbitrary for InternationalLocalPart {
+        fn arbitrary(g: &mut Gen) -> Self {
+            // At most 4 bytes each, within the 64 bytes local parts may have
+            let length = 1 + usize::arbitrary(g) % 16;
+            let local_part = (0..length)
+                .map(|_| loop {
+                    let c = char::arbitrary(g);
+                    if !c.is_ascii() && c.is_alphabetic() {
+                        break c;
+                    }
+                })
+                .collect();
+            Self(local_part)
+        }
+    }
+
+    #[quickcheck]
+    fn valid_emails_are_parsed_successfully(email: ValidEmailFixture) -> bool {
+        SubscriberEmail::parse(email.0).is_ok()
+    }
+
+    #[quickcheck]
+    fn parsing_a_parsed_email_changes_nothing(email: ValidEmailFixture) -> bool {
+        let parsed = SubscriberEmail::parse(email.0).unwrap();
+        let reparsed = SubscriberEmail::parse(parsed.as_ref().to_string()).unwrap();
+
+        parsed.as_ref() == reparsed.as_ref() && parsed.canonical() == reparsed.canonical()
+    }
+
+    #[quickcheck]
+    fn international_local_parts_are_accepted(local_part: InternationalLocalPart) -> bool {
+        let email = format!("{}@bücher.de", local_part.0);
+
+        SubscriberEmail::parse(email).is_ok()
+    }
+
+    #[quickcheck]
+    fn international_local_parts_with_invisible_characters_are_rejected(
+        local_part: InternationalLocalPart,
+    ) -> bool {
+        let email = format!("{}\u{200D}@example.com", local_part.0);
+
+        SubscriberEmail::parse(email).is_err()
+    }
+
+    #[test]
+    fn internationalized_addresses_are_accepted() {
+        for email in [
+            "用户@例子.广告",
+            "пользователь@пример.рф",
+            "josé.garcía@example.es",
+            "χρήστης+news@παράδειγμα.ελ",
+        ] {
+            assert_ok!(SubscriberEmail::parse(email.to_string()), "{}", email);
+        }
+    }
+
+    #[test]
+    fn malformed_internationalized_addresses_are_rejected() {
+        for email in [
+            ".josé@example.com",
+            "josé.@example.com",
+            "jo..sé@example.com",
+            "jo sé@example.com",
+            "用户@",
+            &format!("{}@example.com", "é".repeat(33)),
+        ] {
+            assert_err!(SubscriberEmail::parse(email.to_string()), "{}", email);
+        }
+    }
 
     #[test]
     fn basic_valid_email_is_accepted() {
@@ -61,4 +240,45 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn domain_is_the_part_after_the_at_symbol() {
+        let email = SubscriberEmail::parse("ursula@Example.com".to_string()).unwrap();
+        assert_eq!(email.domain(), "example.com");
+    }
+
+    #[test]
+    fn domains_are_normalized_but_local_parts_kept() {
+        let email = SubscriberEmail::parse("Ursula.Le.Guin@Example.COM".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula.Le.Guin@example.com");
+
+        let email = SubscriberEmail::parse("ursula@Bücher.de".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn spellings_of_the_same_mailbox_have_the_same_canonical_form() {
+        let canonical = |email: &str| {
+            SubscriberEmail::parse(email.to_string())
+                .unwrap()
+                .canonical()
+        };
+
+        assert_eq!(canonical("User@Example.COM"), canonical("user@example.com"));
+        assert_eq!(
+            canonical("Ursula.Le.Guin+news@gmail.com"),
+            "ursulaleguin@gmail.com"
+        );
+        assert_eq!(
+            canonical("ursulaleguin@googlemail.com"),
+            "ursulaleguin@gmail.com"
+        );
+        assert_eq!(canonical("ursula+news@outlook.com"), "ursula@outlook.com");
+    }
+
+    #[test]
+    fn other_providers_keep_dots_and_tags() {
+        let email = SubscriberEmail::parse("Ursula.Le+Guin@example.com".to_string()).unwrap();
+        assert_eq!(email.canonical(), "ursula.le+guin@example.com");
+    }
 }
@@ -1,15 +1,20 @@
+use unicode_general_category::{get_general_category, GeneralCategory};
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
 
 /// The name of a subscriber. Enforces invariants of a valid subscriber name, so
 /// if you have an instance of this, the name is guaranteed to be valid.
 ///
+/// Names are NFC normalized, with whitespace trimmed and collapsed to single
+/// spaces, so names that look the same are stored the same.
+///
 /// # Examples
 /// Use the `parse` function to build a `SubscriberName` from a string.
 /// We can then get the name back out using the `AsRef<str>` implementation.
 /// ```
 /// use zero2prod::domain::SubscriberName;
 ///
-/// let name = SubscriberName::parse("A valid name".to_string()).unwrap();
+/// let name = SubscriberName::parse("  A   valid name ".to_string()).unwrap();
 /// assert_eq!("A valid name", name.as_ref());
 /// ```
 #[derive(Debug)]
@@ -23,23 +28,42 @@ impl SubscriberName {
     /// * It is all whitespace (or empty)
     /// * It has more than 256 characters
     /// * Contains any of `/`, `(`, `)`, `"`, `<`, `>`, `\`, `{`, or `}`
+    /// * Contains control or format characters, like bidi overrides and zero width
+    ///   joiners, which can make names look like something they aren't
     pub fn parse(s: String) -> Result<Self, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
+        let name = s
+            .nfc()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
 
         // graphemes are the visible characters in a unicode string
-        let is_too_long = s.graphemes(true).count() > 256;
+        let is_too_long = name.graphemes(true).count() > 256;
 
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
+        let contains_forbidden_characters = name
+            .chars()
+            .any(|c| forbidden_characters.contains(&c) || is_invisible(c));
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
+        if name.is_empty() || is_too_long || contains_forbidden_characters {
             Err(format!("{} is not a valid subscriber name.", s))
         } else {
-            Ok(Self(s))
+            Ok(Self(name))
         }
     }
 }
 
+/// Whether `c` is a control or format character. Format characters include the
+/// bidi overrides, zero width joiners and other characters that change how text
+/// around them is shown, without being shown themselves.
+pub(crate) fn is_invisible(c: char) -> bool {
+    matches!(
+        get_general_category(c),
+        GeneralCategory::Control | GeneralCategory::Format
+    )
+}
+
 impl AsRef<str> for SubscriberName {
     fn as_ref(&self) -> &str {
         &self.0
@@ -50,6 +74,123 @@ impl AsRef<str> for SubscriberName {
 mod tests {
     use crate::domain::SubscriberName;
     use claim::{assert_err, assert_ok};
+    use quickcheck::{Arbitrary, Gen, TestResult};
+    use quickcheck_macros::quickcheck;
+    use unicode_normalization::UnicodeNormalization;
+
+    use super::is_invisible;
+
+    /// A name of a few words, with letters beyond ASCII
+    #[derive(Debug, Clone)]
+    struct ValidName(String);
+
+    impl Arbitrary for ValidName {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let letters: Vec<char> = "abcdefghijklmnopqrstuvwxyzABCXYZéèêëàâäöüçñßøåÉÖÅΩωЖжя日本語"
+                .chars()
+                .collect();
+            let words = 1 + usize::arbitrary(g) % 4;
+            let name = (0..words)
+                .map(|_| {
+                    let length = 1 + usize::arbitrary(g) % 10;
+                    (0..length)
+                        .map(|_| *g.choose(&letters).unwrap())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            Self(name)
+        }
+    }
+
+    /// Characters that don't show, but change how text around them is shown
+    #[derive(Debug, Clone)]
+    struct InvisibleChar(char);
+
+    impl Arbitrary for InvisibleChar {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let invisible = [
+                '\u{0007}', // bell
+                '\u{001B}', // escape
+                '\u{00AD}', // soft hyphen
+                '\u{200B}', // zero width space
+                '\u{200D}', // zero width joiner
+                '\u{202D}', // left-to-right override
+                '\u{202E}', // right-to-left override
+                '\u{2066}', // left-to-right isolate
+                '\u{FEFF}', // zero width no-break space
+            ];
+            Self(*g.choose(&invisible).unwrap())
+        }
+    }
+
+    /// Runs of whitespace, of the kinds people paste into forms
+    #[derive(Debug, Clone)]
+    struct Whitespace(String);
+
+    impl Arbitrary for Whitespace {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let whitespace = [' ', '\t', '\n', '\u{00A0}', '\u{3000}'];
+            let length = 1 + usize::arbitrary(g) % 4;
+            Self(
+                (0..length)
+                    .map(|_| *g.choose(&whitespace).unwrap())
+                    .collect(),
+            )
+        }
+    }
+
+    #[quickcheck]
+    fn valid_names_are_accepted(name: ValidName) -> bool {
+        SubscriberName::parse(name.0).is_ok()
+    }
+
+    #[quickcheck]
+    fn accepted_names_are_normalized(s: String) -> TestResult {
+        let name = match SubscriberName::parse(s) {
+            Ok(name) => name.0,
+            Err(_) => return TestResult::discard(),
+        };
+
+        TestResult::from_bool(
+            name == name.nfc().collect::<String>()
+                && name == name.trim()
+                && !name.contains("  ")
+                && !name.chars().any(is_invisible),
+        )
+    }
+
+    #[quickcheck]
+    fn names_with_invisible_characters_are_rejected(
+        name: ValidName,
+        c: InvisibleChar,
+        position: usize,
+    ) -> bool {
+        let mut chars: Vec<char> = name.0.chars().collect();
+        chars.insert(position % (chars.len() + 1), c.0);
+
+        SubscriberName::parse(chars.into_iter().collect()).is_err()
+    }
+
+    #[quickcheck]
+    fn whitespace_is_trimmed_and_collapsed(
+        name: ValidName,
+        before: Whitespace,
+        between: Whitespace,
+        after: Whitespace,
+    ) -> bool {
+        let padded = format!("{}{}{}", before.0, name.0.replace(' ', &between.0), after.0);
+
+        SubscriberName::parse(padded).unwrap().0 == name.0
+    }
+
+    #[quickcheck]
+    fn decomposed_and_composed_spellings_are_the_same_name(name: ValidName) -> bool {
+        let decomposed = SubscriberName::parse(name.0.nfd().collect()).unwrap();
+        let composed = SubscriberName::parse(name.0.nfc().collect()).unwrap();
+
+        decomposed.0 == composed.0
+    }
 
     #[test]
     fn a_256_grapheme_long_name_is_valid() {
@@ -0,0 +1,53 @@
+/// Where a subscriber signed up, e.g. which partner site's embedded widget. Stored
+/// in the `source` column of `subscriptions`.
+///
+/// Sources are chosen by whoever embeds the widget, so only short slugs of ASCII
+/// letters, digits, `-` and `_` are accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionSource(String);
+
+impl SubscriptionSource {
+    pub fn parse(s: String) -> Result<Self, String> {
+        let is_slug = s
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if s.is_empty() || s.len() > 64 || !is_slug {
+            Err(format!("{} is not a valid subscription source.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriptionSource {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use super::SubscriptionSource;
+
+    #[test]
+    fn slugs_are_valid() {
+        assert_ok!(SubscriptionSource::parse("partner-blog_2".to_string()));
+        assert_ok!(SubscriptionSource::parse("a".repeat(64)));
+    }
+
+    #[test]
+    fn empty_or_long_sources_are_rejected() {
+        assert_err!(SubscriptionSource::parse("".to_string()));
+        assert_err!(SubscriptionSource::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn sources_with_other_characters_are_rejected() {
+        for source in ["partner blog", "<script>", "blog/2", "blög"] {
+            assert_err!(SubscriptionSource::parse(source.to_string()));
+        }
+    }
+}
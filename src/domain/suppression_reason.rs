@@ -0,0 +1,25 @@
+/// Why we stopped sending email to a subscriber. Stored in the `suppression_reason`
+/// column of `subscriptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    /// The receiving server told us the address doesn't exist or can't ever
+    /// accept mail.
+    HardBounce,
+    /// The recipient reported one of our emails as spam.
+    SpamComplaint,
+    /// The address soft bounced too many times.
+    RepeatedSoftBounces,
+    /// Suppressed by hand, e.g. through the Postmark dashboard.
+    Manual,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HardBounce => "hard_bounce",
+            Self::SpamComplaint => "spam_complaint",
+            Self::RepeatedSoftBounces => "repeated_soft_bounces",
+            Self::Manual => "manual",
+        }
+    }
+}
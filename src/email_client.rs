@@ -1,79 +1,509 @@
-use std::time::Duration;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
-use reqwest::Client;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+
+use rand::Rng;
+use reqwest::{Client, Response, StatusCode};
 use secrecy::{ExposeSecret, Secret};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    circuit_breaker::{CircuitBreaker, CircuitState},
+    dkim::DkimSigner,
+    domain::SubscriberEmail,
+    email_message::EmailMessage,
+    rate_limiter::SendRateLimiter,
+    suppression::UnsuppressedMessages,
+};
 
 /// An email client that can send email to recipients on our behalf.
+///
+/// Messages go out through the first of our providers that is up. The others are
+/// only used while the ones before them are failing.
 pub struct EmailClient {
     sender: SubscriberEmail,
+    providers: Vec<EmailProvider>,
+    retry_policy: RetryPolicy,
+    rate_limiter: SendRateLimiter,
+    dkim_signer: Option<DkimSigner>,
+}
+
+/// A service with a Postmark-compatible API that sends email for us.
+pub struct EmailProvider {
+    name: String,
     http_client: Client,
     base_url: Url,
     authorization_token: Secret<String>,
+    circuit_breaker: CircuitBreaker,
 }
 
-impl EmailClient {
-    /// Creates an email client. Emails will be sent from `sender`.
-    ///
-    /// `base_url` is a URL where requests can be sent to the client. `authorization_token`
-    /// is used to authorize all requests to the client.
+/// Details about an email the provider accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// Name of the provider that took the email
+    pub provider: String,
+    /// The provider's ID for the email. Bounce and other webhooks refer to the email
+    /// by this ID. `None` if the provider's response didn't include one.
+    pub message_id: Option<String>,
+    /// When the provider accepted the email
+    pub submitted_at: Option<DateTime<Utc>>,
+}
+
+/// How hard the email client tries before giving up on a transient failure.
+///
+/// The delay before retry `n` (counting from zero) is drawn at random from the
+/// upper half of `base_delay * 2^n`, capped at `max_delay`. The jitter keeps a
+/// burst of failed requests from retrying in lockstep.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt. Zero disables retrying.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    /// How long to wait before making retry number `retry` (starting at zero).
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let half = exponential / 2;
+        let jitter_ms = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+
+        half + Duration::from_millis(jitter_ms)
+    }
+}
+
+/// Ways in which sending an email can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum EmailClientError {
+    /// Postmark refused the message, and sending it again won't change that. For
+    /// example, the recipient is marked inactive or the address is invalid.
+    #[error("The email provider rejected the message (ErrorCode {error_code}): {message}")]
+    Rejected { error_code: u32, message: String },
+    /// A failure that might go away on its own: a timeout, a dropped connection,
+    /// rate limiting, or a 5xx from the provider.
+    #[error("Transient failure while communicating with the email provider")]
+    Transient(#[source] reqwest::Error),
+    /// Anything else, e.g. an unexpected status code without a Postmark error body.
+    #[error("Failed to send email")]
+    Unexpected(#[source] reqwest::Error),
+    /// Too many recent requests to the provider failed, so we didn't even try.
+    #[error("The email provider circuit breaker is open")]
+    CircuitOpen,
+}
+
+impl EmailClientError {
+    /// Whether it is worth trying to send the same message again.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Transient(_) | Self::CircuitOpen)
+    }
+}
+
+/// Postmark won't accept more than this many messages in one batch request.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// What happened to one message of a batch.
+#[derive(Debug)]
+pub struct BatchSendResult {
+    pub recipient: SubscriberEmail,
+    pub outcome: Result<Delivery, BatchSendError>,
+}
+
+/// Ways in which a single message of a batch can fail.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum BatchSendError {
+    /// Postmark accepted the batch, but refused this particular message.
+    #[error("The email provider rejected the message (ErrorCode {error_code}): {message}")]
+    Rejected { error_code: u32, message: String },
+    /// The whole request carrying this message failed. Shared by every message
+    /// in the same request.
+    #[error("The batch request carrying this message failed")]
+    RequestFailed(#[source] Arc<EmailClientError>),
+    /// Postmark's response didn't include an entry for this message.
+    #[error("The email provider did not report on this message")]
+    MissingResult,
+}
+
+impl BatchSendError {
+    /// Whether it is worth trying to send the same message again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::RequestFailed(err) => err.is_transient(),
+            Self::Rejected { .. } | Self::MissingResult => false,
+        }
+    }
+}
+
+impl EmailProvider {
+    /// Describes a provider called `name`, with its API at `base_url`.
+    /// `authorization_token` is used to authorize all requests to the provider.
     ///
-    /// `timeout` is the timeout for sending an email address
+    /// Requests that take longer than `timeout` are abandoned. Every attempt is
+    /// reported to `circuit_breaker`, which makes us skip the provider while it is
+    /// down.
     pub fn new(
+        name: String,
         base_url: Url,
-        sender: SubscriberEmail,
         authorization_token: Secret<String>,
         timeout: Duration,
+        circuit_breaker: CircuitBreaker,
     ) -> Self {
         let http_client = Client::builder().timeout(timeout).build().unwrap();
 
         Self {
-            sender,
-            base_url,
+            name,
             http_client,
+            base_url,
             authorization_token,
+            circuit_breaker,
         }
     }
 
-    /// Sends an email to `recipient`. The subject line will be `subject`.
-    ///
-    /// Tries to use `html_content` for the body, but will fall back to `text_content`
-    /// if the recipient doesn't support HTML in the body.
-    ///
-    /// Returns an `Err` if there is a failure communicating with the email client,
-    /// including timeout.
-    pub async fn send_email(
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The state of the circuit breaker guarding this provider.
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker.state()
+    }
+
+    /// Makes a single attempt at POSTing `body` to `path` on this provider, unless
+    /// the circuit breaker says not to.
+    async fn try_post<T: Serialize + ?Sized>(
         &self,
-        recipient: SubscriberEmail,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = self.base_url.join("email").unwrap();
-        let body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: html_content,
-            text_body: text_content,
-        };
+        path: &str,
+        body: &T,
+    ) -> Result<Response, EmailClientError> {
+        if !self.circuit_breaker.try_acquire() {
+            return Err(EmailClientError::CircuitOpen);
+        }
+
+        let outcome = self.send_request(path, body).await;
+        match &outcome {
+            Err(EmailClientError::Transient(_)) => self.circuit_breaker.record_failure(),
+            // Anything else means the provider is up and answering
+            _ => self.circuit_breaker.record_success(),
+        }
+
+        outcome
+    }
 
-        let _ = self
+    /// Sends `body` to `path` and classifies the outcome.
+    async fn send_request<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Response, EmailClientError> {
+        let url = self.base_url.join(path).unwrap();
+        let response = self
             .http_client
             .post(url)
             .header(
                 "X-Postmark-Server-Token",
                 self.authorization_token.expose_secret(),
             )
-            .json(&body)
+            .json(body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await
+            .map_err(|err| {
+                if err.is_timeout() || err.is_connect() || err.is_request() {
+                    EmailClientError::Transient(err)
+                } else {
+                    EmailClientError::Unexpected(err)
+                }
+            })?;
+
+        check_response(response).await
+    }
+}
+
+impl EmailClient {
+    /// Creates an email client. Emails will be sent from `sender`.
+    ///
+    /// `providers` are tried in order: we fail over to the next one when a provider
+    /// fails in a transient way, and go back to earlier ones as soon as they are
+    /// healthy again. When every provider fails, we start over according to
+    /// `retry_policy`.
+    ///
+    /// Every email waits on `rate_limiter` before it goes out, retries don't.
+    ///
+    /// Panics if `providers` is empty.
+    pub fn new(
+        sender: SubscriberEmail,
+        providers: Vec<EmailProvider>,
+        retry_policy: RetryPolicy,
+        rate_limiter: SendRateLimiter,
+    ) -> Self {
+        assert!(
+            !providers.is_empty(),
+            "An email client needs at least one provider"
+        );
+
+        Self {
+            sender,
+            providers,
+            retry_policy,
+            rate_limiter,
+            dkim_signer: None,
+        }
+    }
+
+    /// Signs complete MIME messages with `signer`, for transports that hand those
+    /// over. API providers like Postmark sign for us.
+    pub fn with_dkim_signer(mut self, signer: DkimSigner) -> Self {
+        self.dkim_signer = Some(signer);
+        self
+    }
+
+    pub fn dkim_signer(&self) -> Option<&DkimSigner> {
+        self.dkim_signer.as_ref()
+    }
+
+    /// The providers we send through, in order of preference.
+    pub fn providers(&self) -> &[EmailProvider] {
+        &self.providers
+    }
+
+    /// Sends `message` from our sender address.
+    ///
+    /// Transient failures are retried with exponential backoff. Returns an `Err` if
+    /// the provider permanently rejects the message, or if we run out of retries.
+    pub async fn send_email(&self, message: &EmailMessage) -> Result<Delivery, EmailClientError> {
+        let body = SendEmailRequest::new(&self.sender, message);
+
+        self.rate_limiter.acquire(message.to.domain()).await;
+        let (response, provider) = self.post_with_retries("email", &body).await?;
+
+        match response.json::<PostmarkSendResponse>().await {
+            Ok(PostmarkSendResponse {
+                error_code: 0,
+                message_id,
+                submitted_at,
+                ..
+            }) => Ok(Delivery {
+                provider: provider.name.clone(),
+                message_id,
+                submitted_at,
+            }),
+            Ok(PostmarkSendResponse {
+                error_code,
+                message,
+                ..
+            }) => Err(EmailClientError::Rejected {
+                error_code,
+                message,
+            }),
+            // The email is out, losing track of its ID shouldn't make us send it again
+            Err(err) => {
+                tracing::warn!(error.cause_chain = ?err, "Failed to parse send response");
+                Ok(Delivery {
+                    provider: provider.name.clone(),
+                    message_id: None,
+                    submitted_at: None,
+                })
+            }
+        }
+    }
+
+    /// Sends every message in `messages` through Postmark's batch API, splitting them
+    /// into requests of at most `MAX_BATCH_SIZE` messages. Messages to suppressed
+    /// addresses have to be left out first, with `UnsuppressedMessages::filter`.
+    ///
+    /// Returns one result per message, in the same order as `messages`. A failed
+    /// request fails every message it carried, but leaves the other chunks alone, so
+    /// callers can retry or mark only the recipients that didn't go out.
+    pub async fn send_batch(&self, messages: UnsuppressedMessages) -> Vec<BatchSendResult> {
+        let mut results = Vec::with_capacity(messages.len());
+
+        let mut messages = messages.into_inner().into_iter().peekable();
+        while messages.peek().is_some() {
+            let chunk: Vec<EmailMessage> = messages.by_ref().take(MAX_BATCH_SIZE).collect();
+            results.extend(self.send_batch_chunk(chunk).await);
+        }
+
+        results
+    }
+
+    /// Sends a single request to the batch API. `chunk` must not be larger than
+    /// `MAX_BATCH_SIZE`.
+    #[tracing::instrument(
+        name = "Sending a batch of emails",
+        skip(self, chunk),
+        fields(batch_size = chunk.len())
+    )]
+    async fn send_batch_chunk(&self, chunk: Vec<EmailMessage>) -> Vec<BatchSendResult> {
+        let body: Vec<SendEmailRequest> = chunk
+            .iter()
+            .map(|message| SendEmailRequest::new(&self.sender, message))
+            .collect();
+
+        for message in &chunk {
+            self.rate_limiter.acquire(message.to.domain()).await;
+        }
+
+        let (response, provider) = match self.post_with_retries("email/batch", &body).await {
+            Ok(sent) => sent,
+            Err(err) => {
+                tracing::error!(error.cause_chain = ?err, "Failed to send a batch of emails");
+                let err = Arc::new(err);
+                return chunk
+                    .into_iter()
+                    .map(|message| BatchSendResult {
+                        recipient: message.to,
+                        outcome: Err(BatchSendError::RequestFailed(err.clone())),
+                    })
+                    .collect();
+            }
+        };
+        let mut entries = match response.json::<Vec<PostmarkSendResponse>>().await {
+            Ok(entries) => Some(entries.into_iter()),
+            // The emails are out, losing track of their IDs shouldn't make us send them
+            // again
+            Err(err) => {
+                tracing::warn!(error.cause_chain = ?err, "Failed to parse batch send response");
+                None
+            }
+        };
+
+        // Postmark reports on the messages in the order we sent them
+        chunk
+            .into_iter()
+            .map(|message| {
+                let outcome = match entries.as_mut().map(Iterator::next) {
+                    None => Ok(Delivery {
+                        provider: provider.name.clone(),
+                        message_id: None,
+                        submitted_at: None,
+                    }),
+                    Some(Some(PostmarkSendResponse {
+                        error_code: 0,
+                        message_id,
+                        submitted_at,
+                        ..
+                    })) => Ok(Delivery {
+                        provider: provider.name.clone(),
+                        message_id,
+                        submitted_at,
+                    }),
+                    Some(Some(PostmarkSendResponse {
+                        error_code,
+                        message,
+                        ..
+                    })) => Err(BatchSendError::Rejected {
+                        error_code,
+                        message,
+                    }),
+                    Some(None) => Err(BatchSendError::MissingResult),
+                };
+                BatchSendResult {
+                    recipient: message.to,
+                    outcome,
+                }
+            })
+            .collect()
+    }
+
+    /// POSTs `body` to `path` on the first provider that takes it, retrying
+    /// transient failures according to our `RetryPolicy`. Returns the first
+    /// successful response, along with the provider that sent it.
+    async fn post_with_retries<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<(Response, &EmailProvider), EmailClientError> {
+        let mut retry = 0;
+        loop {
+            match self.post_with_failover(path, body).await {
+                Err(err @ EmailClientError::Transient(_))
+                    if retry < self.retry_policy.max_retries =>
+                {
+                    let delay = self.retry_policy.backoff(retry);
+                    tracing::warn!(
+                        error.cause_chain = ?err,
+                        retry = retry + 1,
+                        delay_ms = delay.as_millis() as u64,
+                        "Transient failure sending email, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Makes one pass over our providers, in order, until one of them accepts `body`
+    /// or rejects it permanently.
+    ///
+    /// If every provider fails, returns the last transient error. Returns
+    /// `CircuitOpen` if no provider was even tried.
+    async fn post_with_failover<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<(Response, &EmailProvider), EmailClientError> {
+        let mut last_err = EmailClientError::CircuitOpen;
+        for provider in &self.providers {
+            match provider.try_post(path, body).await {
+                Ok(response) => {
+                    tracing::info!(provider = %provider.name, "Email provider accepted request");
+                    return Ok((response, provider));
+                }
+                Err(EmailClientError::CircuitOpen) => {}
+                Err(err @ EmailClientError::Transient(_)) => {
+                    tracing::warn!(
+                        error.cause_chain = ?err,
+                        provider = %provider.name,
+                        "Email provider failed, trying the next one"
+                    );
+                    last_err = err;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(last_err)
+    }
+}
 
-        Ok(())
+/// Maps a response from Postmark to the appropriate `EmailClientError`. Successful
+/// responses are handed back so the caller can read the body.
+async fn check_response(response: Response) -> Result<Response, EmailClientError> {
+    let status_err = match response.error_for_status_ref() {
+        Ok(_) => return Ok(response),
+        Err(err) => err,
+    };
+
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        return Err(EmailClientError::Transient(status_err));
+    }
+
+    // Postmark reports request-specific problems as a 4xx with a JSON body.
+    match response.json::<PostmarkErrorResponse>().await {
+        Ok(PostmarkErrorResponse {
+            error_code,
+            message,
+        }) if error_code != 0 => Err(EmailClientError::Rejected {
+            error_code,
+            message,
+        }),
+        _ => Err(EmailClientError::Unexpected(status_err)),
     }
 }
 
@@ -83,24 +513,131 @@ impl EmailClient {
 struct SendEmailRequest<'a> {
     from: &'a str,
     to: &'a str,
+    /// Comma separated addresses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bcc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_stream: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<PostmarkHeader<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<PostmarkAttachment<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkAttachment<'a> {
+    name: &'a str,
+    /// Base64 encoded
+    content: String,
+    content_type: &'a str,
+}
+
+impl<'a> SendEmailRequest<'a> {
+    fn new(sender: &'a SubscriberEmail, message: &'a EmailMessage) -> Self {
+        let address_list = |addresses: &[SubscriberEmail]| {
+            (!addresses.is_empty()).then(|| {
+                addresses
+                    .iter()
+                    .map(|address| address.as_ref())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+        };
+
+        Self {
+            from: sender.as_ref(),
+            to: message.to.as_ref(),
+            cc: address_list(&message.cc),
+            bcc: address_list(&message.bcc),
+            reply_to: message.reply_to.as_ref().map(|address| address.as_ref()),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            tag: message.tag.as_deref(),
+            metadata: &message.metadata,
+            message_stream: message.message_stream.as_deref(),
+            headers: message
+                .headers
+                .iter()
+                .map(|(name, value)| PostmarkHeader { name, value })
+                .collect(),
+            attachments: message
+                .attachments
+                .iter()
+                .map(|attachment| PostmarkAttachment {
+                    name: &attachment.name,
+                    content: BASE64.encode(&attachment.content),
+                    content_type: &attachment.content_type,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The body of a send response, or one of the per-message entries of a batch send
+/// response. Anything but an `ErrorCode` of zero means the message was not sent.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkSendResponse {
+    error_code: u32,
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    submitted_at: Option<DateTime<Utc>>,
+}
+
+/// The body Postmark sends back along with a 4xx status.
+///
+/// See <https://postmarkapp.com/developer/api/overview#error-codes> for the codes.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkErrorResponse {
+    error_code: u32,
+    message: String,
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{collections::HashMap, time::Duration};
 
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
-    use claim::{assert_err, assert_ok};
+    use crate::{
+        circuit_breaker::{CircuitBreaker, CircuitBreakerPolicy, CircuitState},
+        domain::SubscriberEmail,
+        email_client::{
+            BatchSendError, EmailClient, EmailClientError, EmailProvider, RetryPolicy,
+            MAX_BATCH_SIZE,
+        },
+        email_message::{Attachment, EmailMessage},
+        rate_limiter::{RateLimit, SendRateLimiter},
+        suppression::UnsuppressedMessages,
+    };
+    use claim::{assert_err, assert_matches, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
     use url::Url;
-    use wiremock::matchers::{any, header, header_exists, method, path};
-    use wiremock::{Match, Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
+    use wiremock::{Match, Mock, MockServer, Respond, ResponseTemplate};
 
     /// A wiremock matcher that checks for requests with the required JSON elements
     /// in the body.
@@ -124,6 +661,50 @@ mod tests {
         }
     }
 
+    /// A wiremock matcher for batch requests: a JSON array in which every element
+    /// would satisfy `EmailBodyMatcher`.
+    struct BatchBodyMatcher;
+
+    impl Match for BatchBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<Vec<serde_json::Value>, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(messages) = result {
+                messages.iter().all(|message| {
+                    ["From", "To", "Subject", "HtmlBody", "TextBody"]
+                        .iter()
+                        .all(|field| message.get(field).is_some())
+                })
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Responds to a batch request the way Postmark does when every message is
+    /// accepted: one successful entry per message.
+    struct AcceptEveryMessage;
+
+    impl Respond for AcceptEveryMessage {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let entries: Vec<_> = messages
+                .iter()
+                .map(|message| {
+                    serde_json::json!({
+                        "ErrorCode": 0,
+                        "Message": "OK",
+                        "To": message["To"],
+                        "MessageID": uuid::Uuid::new_v4(),
+                        "SubmittedAt": "2026-10-18T12:01:05.1794748-04:00",
+                    })
+                })
+                .collect();
+
+            ResponseTemplate::new(200).set_body_json(entries)
+        }
+    }
+
     /// Fake email subject for tests
     fn subject() -> String {
         Sentence(1..2).fake()
@@ -139,16 +720,92 @@ mod tests {
         SubscriberEmail::parse(SafeEmail().fake()).unwrap()
     }
 
-    /// Configure an email client listening at `base_url`
+    /// Fake message for tests
+    fn message() -> EmailMessage {
+        EmailMessage::new(email(), subject(), content(), content())
+    }
+
+    /// Fake batch of `n` messages for tests
+    fn batch(n: usize) -> UnsuppressedMessages {
+        UnsuppressedMessages::unchecked((0..n).map(|_| message()).collect())
+    }
+
+    /// Configure an email client listening at `base_url`. It does not retry.
     fn email_client(base_url: Url) -> EmailClient {
+        email_client_with_retries(base_url, 0)
+    }
+
+    /// Configure an email client listening at `base_url`, retrying up to `max_retries`
+    /// times with negligible backoff.
+    fn email_client_with_retries(base_url: Url, max_retries: u32) -> EmailClient {
+        // A circuit breaker that never opens
+        let circuit_breaker_policy = CircuitBreakerPolicy {
+            failure_rate_threshold: 1.0,
+            window_size: 100,
+            minimum_requests: 100,
+            open_duration: Duration::from_secs(60),
+        };
+
+        build_email_client(base_url, max_retries, circuit_breaker_policy)
+    }
+
+    fn build_email_client(
+        base_url: Url,
+        max_retries: u32,
+        circuit_breaker_policy: CircuitBreakerPolicy,
+    ) -> EmailClient {
+        let providers = vec![provider("primary", base_url, circuit_breaker_policy)];
+        build_failover_client(providers, max_retries)
+    }
+
+    fn build_failover_client(providers: Vec<EmailProvider>, max_retries: u32) -> EmailClient {
+        let retry_policy = RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+
         EmailClient::new(
-            base_url,
             email(),
+            providers,
+            retry_policy,
+            SendRateLimiter::unlimited(),
+        )
+    }
+
+    /// An email provider called `name`, listening at `base_url`
+    fn provider(
+        name: &str,
+        base_url: Url,
+        circuit_breaker_policy: CircuitBreakerPolicy,
+    ) -> EmailProvider {
+        EmailProvider::new(
+            name.into(),
+            base_url,
             Secret::new(Faker.fake()),
             Duration::from_millis(200), // fail fast in tests!
+            CircuitBreaker::new(name.into(), circuit_breaker_policy),
         )
     }
 
+    /// A circuit breaker policy that opens on the first failure, for `open_duration`
+    fn trip_on_first_failure(open_duration: Duration) -> CircuitBreakerPolicy {
+        CircuitBreakerPolicy {
+            failure_rate_threshold: 0.5,
+            window_size: 1,
+            minimum_requests: 1,
+            open_duration,
+        }
+    }
+
+    /// A Postmark error response with the given `ErrorCode`
+    fn postmark_error(error_code: u32) -> ResponseTemplate {
+        ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": error_code,
+            "Message": "Something went wrong"
+        }))
+    }
+
     #[tokio::test]
     async fn send_email_sends_the_expected_request() {
         let mock_server = MockServer::start().await;
@@ -166,13 +823,85 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let _ = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
+        let _ = email_client.send_email(&message()).await;
 
         // Mock::expect above has already handled our assertions
     }
 
+    #[tokio::test]
+    async fn send_email_sends_the_optional_message_fields() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+        let reply_to = email();
+        let cc = [email(), email()];
+        let bcc = email();
+        let message = message()
+            .reply_to(reply_to.clone())
+            .cc(cc[0].clone())
+            .cc(cc[1].clone())
+            .bcc(bcc.clone())
+            .tag("welcome")
+            .metadata("subscriber_id", "42")
+            .message_stream("outbound")
+            .header("X-Campaign", "autumn")
+            .attachment(Attachment::new(
+                "hello.txt",
+                "text/plain",
+                b"hello".to_vec(),
+            ));
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(serde_json::json!({
+                "ReplyTo": reply_to.as_ref(),
+                "Cc": format!("{}, {}", cc[0].as_ref(), cc[1].as_ref()),
+                "Bcc": bcc.as_ref(),
+                "Tag": "welcome",
+                "Metadata": { "subscriber_id": "42" },
+                "MessageStream": "outbound",
+                "Headers": [{ "Name": "X-Campaign", "Value": "autumn" }],
+                "Attachments": [{
+                    "Name": "hello.txt",
+                    "Content": "aGVsbG8=",
+                    "ContentType": "text/plain"
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message).await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_leaves_out_optional_fields_that_are_not_set() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client.send_email(&message()).await.unwrap();
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let mut fields: Vec<&str> = body
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort_unstable();
+        assert_eq!(fields, ["From", "HtmlBody", "Subject", "TextBody", "To"]);
+    }
+
     #[tokio::test]
     async fn send_email_returns_ok_if_server_returns_200() {
         let mock_server = MockServer::start().await;
@@ -186,13 +915,42 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
+        let outcome = email_client.send_email(&message()).await;
 
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_message_id_assigned_by_the_provider() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        let response = ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "receiver@example.com",
+            "SubmittedAt": "2026-10-18T12:01:05.1794748-04:00",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+            "Message": "OK"
+        }));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let delivery = email_client.send_email(&message()).await.unwrap();
+
+        assert_eq!(
+            delivery.message_id.as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+        assert_eq!(
+            delivery.submitted_at.unwrap().to_rfc3339(),
+            "2026-10-18T16:01:05.179474800+00:00"
+        );
+    }
+
     #[tokio::test]
     async fn send_email_returns_err_if_server_returns_500() {
         let mock_server = MockServer::start().await;
@@ -206,9 +964,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
+        let outcome = email_client.send_email(&message()).await;
 
         assert_err!(outcome);
     }
@@ -226,10 +982,623 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
+        let outcome = email_client.send_email(&message()).await;
 
         assert_err!(outcome);
     }
+
+    #[tokio::test]
+    async fn send_email_retries_a_500_until_it_succeeds() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client_with_retries(url, 3);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_when_rate_limited() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client_with_retries(url, 3);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_after_a_timeout() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client_with_retries(url, 3);
+
+        let slow_response = ResponseTemplate::new(200).set_delay(Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(slow_response)
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_after_max_retries() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client_with_retries(url, 2);
+
+        // The first attempt, plus two retries
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_matches!(outcome, Err(EmailClientError::Transient(_)));
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_an_inactive_recipient() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client_with_retries(url, 3);
+
+        Mock::given(any())
+            .respond_with(postmark_error(406))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_matches!(
+            outcome,
+            Err(EmailClientError::Rejected {
+                error_code: 406,
+                ..
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_an_invalid_email_request() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client_with_retries(url, 3);
+
+        Mock::given(any())
+            .respond_with(postmark_error(300))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_matches!(
+            outcome,
+            Err(EmailClientError::Rejected {
+                error_code: 300,
+                ..
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_a_4xx_without_an_error_code() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client_with_retries(url, 3);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_matches!(outcome, Err(EmailClientError::Unexpected(_)));
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .and(BatchBodyMatcher)
+            .respond_with(AcceptEveryMessage)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(batch(3)).await;
+
+        assert_eq!(results.len(), 3);
+        for result in results {
+            assert_ok!(result.outcome);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_a_result_per_recipient() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        let response = ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ErrorCode": 0, "Message": "OK", "MessageID": "first" },
+            { "ErrorCode": 406, "Message": "Inactive recipient" },
+            { "ErrorCode": 0, "Message": "OK", "MessageID": "third" },
+        ]));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let messages: Vec<EmailMessage> = (0..3).map(|_| message()).collect();
+        let recipients: Vec<String> = messages
+            .iter()
+            .map(|m| m.recipient().as_ref().to_owned())
+            .collect();
+        let results = email_client
+            .send_batch(UnsuppressedMessages::unchecked(messages))
+            .await;
+
+        let returned: Vec<&str> = results.iter().map(|r| r.recipient.as_ref()).collect();
+        assert_eq!(returned, recipients);
+        let message_id = |i: usize| results[i].outcome.as_ref().unwrap().message_id.as_deref();
+        assert_eq!(message_id(0), Some("first"));
+        assert_matches!(
+            &results[1].outcome,
+            Err(BatchSendError::Rejected {
+                error_code: 406,
+                ..
+            })
+        );
+        assert_eq!(message_id(2), Some("third"));
+    }
+
+    #[tokio::test]
+    async fn send_batch_splits_large_batches_into_multiple_requests() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        Mock::given(path("/email/batch"))
+            .respond_with(AcceptEveryMessage)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(batch(MAX_BATCH_SIZE + 1)).await;
+
+        assert_eq!(results.len(), MAX_BATCH_SIZE + 1);
+        for result in results {
+            assert_ok!(result.outcome);
+        }
+        let requests = mock_server.received_requests().await.unwrap();
+        let sizes: Vec<usize> = requests
+            .iter()
+            .map(|r| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&r.body)
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![MAX_BATCH_SIZE, 1]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_every_message_of_a_failed_request() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(batch(3)).await;
+
+        assert_eq!(results.len(), 3);
+        for result in results {
+            let err = result.outcome.unwrap_err();
+            assert!(err.is_transient());
+            assert_matches!(err, BatchSendError::RequestFailed(_));
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_flags_messages_missing_from_the_response() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        let response = ResponseTemplate::new(200)
+            .set_body_json(serde_json::json!([{ "ErrorCode": 0, "Message": "OK" }]));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(batch(2)).await;
+
+        assert_ok!(&results[0].outcome);
+        assert_matches!(&results[1].outcome, Err(BatchSendError::MissingResult));
+    }
+
+    #[tokio::test]
+    async fn send_batch_counts_messages_as_delivered_when_the_response_is_garbled() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let email_client = email_client(url);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(batch(2)).await;
+
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let delivery = result.outcome.unwrap();
+            assert_eq!(delivery.provider, "primary");
+            assert_eq!(delivery.message_id, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_fast_once_the_circuit_breaker_opens() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let circuit_breaker_policy = CircuitBreakerPolicy {
+            failure_rate_threshold: 0.5,
+            window_size: 2,
+            minimum_requests: 2,
+            open_duration: Duration::from_secs(60),
+        };
+        let email_client = build_email_client(url, 3, circuit_breaker_policy);
+
+        // Two failed attempts open the circuit, the retries after that never
+        // reach the server
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let first = email_client.send_email(&message()).await;
+        let second = email_client.send_email(&message()).await;
+
+        assert_matches!(first, Err(EmailClientError::CircuitOpen));
+        assert_matches!(second, Err(EmailClientError::CircuitOpen));
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Open
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_closes_the_circuit_after_a_successful_probe() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let circuit_breaker_policy = CircuitBreakerPolicy {
+            failure_rate_threshold: 0.5,
+            window_size: 2,
+            minimum_requests: 2,
+            open_duration: Duration::from_millis(50),
+        };
+        let email_client = build_email_client(url, 0, circuit_breaker_policy);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+        for _ in 0..2 {
+            let _ = email_client.send_email(&message()).await;
+        }
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Open
+        );
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_ok!(outcome);
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_rejections_do_not_open_the_circuit() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let circuit_breaker_policy = CircuitBreakerPolicy {
+            failure_rate_threshold: 0.5,
+            window_size: 2,
+            minimum_requests: 2,
+            open_duration: Duration::from_secs(60),
+        };
+        let email_client = build_email_client(url, 0, circuit_breaker_policy);
+
+        Mock::given(any())
+            .respond_with(postmark_error(406))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        for _ in 0..3 {
+            let outcome = email_client.send_email(&message()).await;
+            assert_matches!(outcome, Err(EmailClientError::Rejected { .. }));
+        }
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_uses_the_primary_provider_while_it_is_healthy() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let policy = trip_on_first_failure(Duration::from_secs(60));
+        let email_client = build_failover_client(
+            vec![
+                provider(
+                    "primary",
+                    Url::parse(&primary.uri()).unwrap(),
+                    policy.clone(),
+                ),
+                provider("secondary", Url::parse(&secondary.uri()).unwrap(), policy),
+            ],
+            0,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&secondary)
+            .await;
+
+        let delivery = email_client.send_email(&message()).await.unwrap();
+
+        assert_eq!(delivery.provider, "primary");
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_over_to_the_next_provider_on_transient_errors() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let policy = trip_on_first_failure(Duration::from_secs(60));
+        let email_client = build_failover_client(
+            vec![
+                provider(
+                    "primary",
+                    Url::parse(&primary.uri()).unwrap(),
+                    policy.clone(),
+                ),
+                provider("secondary", Url::parse(&secondary.uri()).unwrap(), policy),
+            ],
+            0,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&secondary)
+            .await;
+
+        // The second email skips the primary, its circuit is open
+        for _ in 0..2 {
+            let delivery = email_client.send_email(&message()).await.unwrap();
+            assert_eq!(delivery.provider, "secondary");
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_to_the_primary_provider_once_it_is_healthy() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let policy = trip_on_first_failure(Duration::from_millis(50));
+        let email_client = build_failover_client(
+            vec![
+                provider(
+                    "primary",
+                    Url::parse(&primary.uri()).unwrap(),
+                    policy.clone(),
+                ),
+                provider("secondary", Url::parse(&secondary.uri()).unwrap(), policy),
+            ],
+            0,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&secondary)
+            .await;
+        let delivery = email_client.send_email(&message()).await.unwrap();
+        assert_eq!(delivery.provider, "secondary");
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let delivery = email_client.send_email(&message()).await.unwrap();
+
+        assert_eq!(delivery.provider, "primary");
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_fail_over_permanent_rejections() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let policy = trip_on_first_failure(Duration::from_secs(60));
+        let email_client = build_failover_client(
+            vec![
+                provider(
+                    "primary",
+                    Url::parse(&primary.uri()).unwrap(),
+                    policy.clone(),
+                ),
+                provider("secondary", Url::parse(&secondary.uri()).unwrap(), policy),
+            ],
+            0,
+        );
+
+        Mock::given(any())
+            .respond_with(postmark_error(406))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&secondary)
+            .await;
+
+        let outcome = email_client.send_email(&message()).await;
+
+        assert_matches!(outcome, Err(EmailClientError::Rejected { .. }));
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_the_provider_that_delivered_it() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let policy = trip_on_first_failure(Duration::from_secs(60));
+        let email_client = build_failover_client(
+            vec![
+                provider(
+                    "primary",
+                    Url::parse(&primary.uri()).unwrap(),
+                    policy.clone(),
+                ),
+                provider("secondary", Url::parse(&secondary.uri()).unwrap(), policy),
+            ],
+            0,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&primary)
+            .await;
+        Mock::given(path("/email/batch"))
+            .respond_with(AcceptEveryMessage)
+            .expect(1)
+            .mount(&secondary)
+            .await;
+
+        let results = email_client.send_batch(batch(2)).await;
+
+        for result in results {
+            assert_eq!(result.outcome.unwrap().provider, "secondary");
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_waits_on_the_rate_limiter() {
+        let mock_server = MockServer::start().await;
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        let retry_policy = RetryPolicy::none();
+        let rate_limiter = SendRateLimiter::new(Some(RateLimit::per_second(4)), HashMap::new());
+        let policy = trip_on_first_failure(Duration::from_secs(60));
+        let email_client = EmailClient::new(
+            email(),
+            vec![provider("primary", url, policy)],
+            retry_policy,
+            rate_limiter,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(5)
+            .mount(&mock_server)
+            .await;
+
+        // The first four go out right away, the fifth waits for a new token
+        let start = std::time::Instant::now();
+        for _ in 0..5 {
+            email_client.send_email(&message()).await.unwrap();
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
 }
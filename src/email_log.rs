@@ -0,0 +1,203 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::email_client::{Delivery, EmailClientError};
+
+/// Why we sent an email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailPurpose {
+    /// Asking a new subscriber to confirm their subscription
+    Confirmation,
+    /// Delivering the newsletter issue with the given ID
+    Issue(Uuid),
+}
+
+impl EmailPurpose {
+    /// The value of the `purpose` column. Issue IDs go in a column of their own.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmation => "confirmation",
+            Self::Issue(_) => "issue",
+        }
+    }
+
+    pub fn issue_id(&self) -> Option<Uuid> {
+        match self {
+            Self::Confirmation => None,
+            Self::Issue(issue_id) => Some(*issue_id),
+        }
+    }
+
+    /// Reads a purpose back from its `purpose` and `issue_id` columns.
+    pub fn from_columns(purpose: &str, issue_id: Option<Uuid>) -> Result<Self, String> {
+        match (purpose, issue_id) {
+            ("confirmation", _) => Ok(Self::Confirmation),
+            ("issue", Some(issue_id)) => Ok(Self::Issue(issue_id)),
+            ("issue", None) => Err("Issue emails need an issue ID".into()),
+            (other, _) => Err(format!("{} is not a known email purpose", other)),
+        }
+    }
+}
+
+/// Where an email we tried to send stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailStatus {
+    /// The provider accepted the email
+    Sent,
+    /// We couldn't hand the email to the provider
+    Failed,
+    /// The provider accepted the email, but it bounced later on
+    Bounced,
+}
+
+impl EmailStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+            Self::Bounced => "bounced",
+        }
+    }
+}
+
+/// A row of the `email_log` table.
+#[derive(Debug)]
+pub struct EmailLogEntry {
+    pub id: Uuid,
+    pub recipient: String,
+    pub subscriber_id: Option<Uuid>,
+    pub purpose: String,
+    pub issue_id: Option<Uuid>,
+    pub provider: Option<String>,
+    pub message_id: Option<String>,
+    pub status: String,
+    pub error: Option<String>,
+    pub logged_at: DateTime<Utc>,
+}
+
+/// Records an attempt at sending an email to `recipient`, and its `outcome`.
+///
+/// `subscriber_id` is `None` for recipients we don't know as subscribers.
+#[tracing::instrument(
+    name = "Saving email in the email log",
+    skip(executor, recipient, outcome)
+)]
+pub async fn log_email(
+    executor: impl Executor<'_, Database = Postgres>,
+    recipient: &str,
+    subscriber_id: Option<Uuid>,
+    purpose: EmailPurpose,
+    outcome: &Result<Delivery, EmailClientError>,
+) -> Result<(), sqlx::Error> {
+    let (status, provider, message_id, error) = match outcome {
+        Ok(delivery) => (
+            EmailStatus::Sent,
+            Some(delivery.provider.as_str()),
+            delivery.message_id.as_deref(),
+            None,
+        ),
+        Err(err) => (EmailStatus::Failed, None, None, Some(err.to_string())),
+    };
+
+    sqlx::query!(
+        r#"INSERT INTO email_log (id, recipient, subscriber_id, purpose, issue_id, provider,
+            message_id, status, error, logged_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#,
+        Uuid::new_v4(),
+        recipient,
+        subscriber_id,
+        purpose.as_str(),
+        purpose.issue_id(),
+        provider,
+        message_id,
+        status.as_str(),
+        error,
+        Utc::now()
+    )
+    .execute(executor)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+/// Marks the email the provider knows as `message_id` as bounced, with the bounce
+/// type as the error.
+#[tracing::instrument(name = "Marking email as bounced in the email log", skip(executor))]
+pub async fn mark_bounced(
+    executor: impl Executor<'_, Database = Postgres>,
+    message_id: &str,
+    bounce_type: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE email_log SET status = $1, error = $2 WHERE message_id = $3",
+        EmailStatus::Bounced.as_str(),
+        bounce_type,
+        message_id
+    )
+    .execute(executor)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+/// Every email we tried to send to the subscriber with ID `subscriber_id`, most
+/// recent first.
+#[tracing::instrument(name = "Fetching email log of subscriber", skip(pool))]
+pub async fn email_log_for_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<EmailLogEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailLogEntry,
+        r#"SELECT id, recipient, subscriber_id, purpose, issue_id, provider, message_id,
+            status, error, logged_at
+        FROM email_log
+        WHERE subscriber_id = $1
+        ORDER BY logged_at DESC"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+    use uuid::Uuid;
+
+    use super::EmailPurpose;
+
+    #[test]
+    fn purposes_round_trip_through_their_columns() {
+        for purpose in [
+            EmailPurpose::Confirmation,
+            EmailPurpose::Issue(Uuid::new_v4()),
+        ] {
+            let read_back = EmailPurpose::from_columns(purpose.as_str(), purpose.issue_id());
+            assert_eq!(read_back, Ok(purpose));
+        }
+    }
+
+    #[test]
+    fn issue_purposes_need_an_issue_id() {
+        assert_err!(EmailPurpose::from_columns("issue", None));
+    }
+
+    #[test]
+    fn unknown_purposes_are_rejected() {
+        assert_err!(EmailPurpose::from_columns("newsletter", None));
+    }
+}
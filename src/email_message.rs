@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use crate::domain::SubscriberEmail;
+
+/// An email to send through the `EmailClient`. The sender is always the one the
+/// client was configured with.
+///
+/// Only the recipient, subject and bodies are required. Everything else is set with
+/// the builder methods.
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub(crate) to: SubscriberEmail,
+    pub(crate) subject: String,
+    pub(crate) html_body: String,
+    pub(crate) text_body: String,
+    pub(crate) reply_to: Option<SubscriberEmail>,
+    pub(crate) cc: Vec<SubscriberEmail>,
+    pub(crate) bcc: Vec<SubscriberEmail>,
+    pub(crate) tag: Option<String>,
+    pub(crate) metadata: BTreeMap<String, String>,
+    pub(crate) message_stream: Option<String>,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) attachments: Vec<Attachment>,
+}
+
+/// A file attached to an `EmailMessage`.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub(crate) name: String,
+    pub(crate) content_type: String,
+    pub(crate) content: Vec<u8>,
+}
+
+impl Attachment {
+    /// A file called `name` holding `content`. `content_type` is its MIME type,
+    /// e.g. `application/pdf`.
+    pub fn new(name: impl Into<String>, content_type: impl Into<String>, content: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            content_type: content_type.into(),
+            content,
+        }
+    }
+}
+
+impl EmailMessage {
+    /// An email to `to` with the given subject line.
+    ///
+    /// Recipients see `html_body`, unless their client doesn't support HTML, in which
+    /// case they see `text_body`.
+    pub fn new(
+        to: SubscriberEmail,
+        subject: impl Into<String>,
+        html_body: impl Into<String>,
+        text_body: impl Into<String>,
+    ) -> Self {
+        Self {
+            to,
+            subject: subject.into(),
+            html_body: html_body.into(),
+            text_body: text_body.into(),
+            reply_to: None,
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            tag: None,
+            metadata: BTreeMap::new(),
+            message_stream: None,
+            headers: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn recipient(&self) -> &SubscriberEmail {
+        &self.to
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Where replies should go, if not to the sender.
+    pub fn reply_to(mut self, address: SubscriberEmail) -> Self {
+        self.reply_to = Some(address);
+        self
+    }
+
+    /// Adds `address` to the CC recipients.
+    pub fn cc(mut self, address: SubscriberEmail) -> Self {
+        self.cc.push(address);
+        self
+    }
+
+    /// Adds `address` to the BCC recipients.
+    pub fn bcc(mut self, address: SubscriberEmail) -> Self {
+        self.bcc.push(address);
+        self
+    }
+
+    /// Categorizes the email for the provider's statistics.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Attaches a key/value pair the provider hands back to us in webhooks about
+    /// this email. Setting the same key twice keeps the last value.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sends the email through the given Postmark message stream, which keeps
+    /// transactional and broadcast email apart. Without one, Postmark uses its
+    /// default transactional stream.
+    pub fn message_stream(mut self, stream: impl Into<String>) -> Self {
+        self.message_stream = Some(stream.into());
+        self
+    }
+
+    /// Adds a custom header to the email.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+}
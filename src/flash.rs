@@ -0,0 +1,88 @@
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    HttpRequest,
+};
+use secrecy::Secret;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::signing::{self, Purpose};
+
+/// The cookie holding the signed flash message
+pub const FLASH_COOKIE: &str = "_flash";
+
+/// The cookie for a redirect showing `message` on the next page.
+///
+/// Flash messages carry the outcome of a form submission over to the page the
+/// browser is redirected to, for post/redirect/get. They're signed, so pages can
+/// trust that what they display came from us.
+pub fn flash_cookie<T: Serialize>(
+    message: &T,
+    secret: &Secret<String>,
+    secure: bool,
+) -> Cookie<'static> {
+    Cookie::build(FLASH_COOKIE, signing::sign(Purpose::Flash, message, secret))
+        .path("/")
+        .http_only(true)
+        .secure(secure)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::minutes(5))
+        .finish()
+}
+
+/// The flash message sent along with `request`, if any. Forged or garbled messages
+/// are ignored. Pages showing the message should send `removal_cookie` back, so
+/// it's only shown once.
+pub fn read_flash<T: DeserializeOwned>(
+    request: &HttpRequest,
+    secret: &Secret<String>,
+) -> Option<T> {
+    let cookie = request.cookie(FLASH_COOKIE)?;
+    match signing::verify(Purpose::Flash, cookie.value(), secret) {
+        Ok(message) => Some(message),
+        Err(err) => {
+            tracing::warn!(error.cause_chain = ?err, "Ignored invalid flash message");
+            None
+        }
+    }
+}
+
+/// Deletes the flash message cookie from the browser.
+pub fn removal_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::build(FLASH_COOKIE, "").path("/").finish();
+    cookie.make_removal();
+    cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use secrecy::Secret;
+
+    use super::{flash_cookie, read_flash};
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn flash_messages_round_trip_through_their_cookie() {
+        let request = TestRequest::default()
+            .cookie(flash_cookie(&"Welcome!", &secret(), true))
+            .to_http_request();
+
+        assert_eq!(
+            read_flash::<String>(&request, &secret()),
+            Some("Welcome!".to_string())
+        );
+    }
+
+    #[test]
+    fn forged_flash_messages_are_ignored() {
+        let other_secret = Secret::new("another-key".to_string());
+        let request = TestRequest::default()
+            .cookie(flash_cookie(&"<script>", &other_secret, true))
+            .to_http_request();
+
+        assert_eq!(read_flash::<String>(&request, &secret()), None);
+    }
+}
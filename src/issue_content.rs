@@ -0,0 +1,361 @@
+use std::borrow::Cow;
+
+use lol_html::{element, rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag};
+
+/// The HTML and plain text versions of a newsletter issue.
+///
+/// Library only for now: nothing renders issues until there is a way to write and
+/// send them. The result is meant to be parsed as an `IssueTemplate`, then tracked
+/// with `add_tracking` for every recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueContent {
+    pub html: String,
+    pub text: String,
+}
+
+impl IssueContent {
+    /// Renders both versions of an issue from the Markdown its author wrote.
+    ///
+    /// The HTML is sanitized, then styled by inlining `stylesheet`, since most
+    /// email clients ignore `<style>` elements. In the text version, links are
+    /// numbered footnotes listed at the end.
+    pub fn from_markdown(
+        markdown: &str,
+        stylesheet: &Stylesheet,
+    ) -> Result<Self, lol_html::errors::RewritingError> {
+        let mut unsafe_html = String::new();
+        html::push_html(
+            &mut unsafe_html,
+            Parser::new_ext(markdown, markdown_options()),
+        );
+        let html = restore_link_merge_fields(&ammonia::clean(&unsafe_html))?;
+        let html = stylesheet.inline(&html)?;
+
+        Ok(Self {
+            html,
+            text: markdown_to_text(markdown),
+        })
+    }
+
+    /// Replaces the HTML version with one written by hand. It's used as is.
+    pub fn with_html(mut self, html: impl Into<String>) -> Self {
+        self.html = html.into();
+        self
+    }
+
+    /// Replaces the text version with one written by hand.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+}
+
+/// Undoes the percent-encoding of merge fields like `{{unsubscribe_link}}` in
+/// link and image URLs, so they get filled in per recipient.
+fn restore_link_merge_fields(html: &str) -> Result<String, lol_html::errors::RewritingError> {
+    let restore = |url: String| url.replace("%7B%7B", "{{").replace("%7D%7D", "}}");
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("a[href]", |el| {
+                    el.set_attribute("href", &restore(el.get_attribute("href").unwrap()))?;
+                    Ok(())
+                }),
+                element!("img[src]", |el| {
+                    el.set_attribute("src", &restore(el.get_attribute("src").unwrap()))?;
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+}
+
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH
+}
+
+/// Plain text for `markdown`, with its formatting stripped.
+fn markdown_to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut links: Vec<String> = Vec::new();
+    // Where each open link points to, `None` if its text is the URL already
+    let mut open_links: Vec<Option<String>> = Vec::new();
+    // The next number of each open list, `None` for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        match event {
+            Event::Start(Tag::List(start)) => {
+                if lists.is_empty() && !text.is_empty() && !text.ends_with("\n\n") {
+                    text.push('\n');
+                }
+                lists.push(start);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::End(Tag::Paragraph) if lists.is_empty() => text.push_str("\n\n"),
+            Event::End(Tag::Heading(..)) | Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(_))) => {
+                text.push_str("\n\n")
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Indented)) => text.push('\n'),
+            Event::End(Tag::TableCell) => text.push('\t'),
+            Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => text.push('\n'),
+            Event::End(Tag::Table(_)) => text.push('\n'),
+            Event::Start(Tag::Link(_, url, _)) => open_links.push(Some(url.to_string())),
+            Event::End(Tag::Link(..)) => {
+                if let Some(Some(url)) = open_links.pop() {
+                    let number = match links.iter().position(|link| *link == url) {
+                        Some(index) => index + 1,
+                        None => {
+                            links.push(url);
+                            links.len()
+                        }
+                    };
+                    text.push_str(&format!(" [{}]", number));
+                }
+            }
+            Event::Text(content) | Event::Code(content) => {
+                // Autolinks like <https://example.com> show their URL already
+                if let Some(url) = open_links.last_mut() {
+                    if url.as_deref() == Some(content.as_ref()) {
+                        *url = None;
+                    }
+                }
+                text.push_str(&content);
+            }
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("---\n\n"),
+            _ => {}
+        }
+    }
+
+    let mut text = text.trim_end().to_string();
+    if !links.is_empty() {
+        text.push_str("\n\n");
+        for (index, url) in links.iter().enumerate() {
+            text.push_str(&format!("[{}] {}\n", index + 1, url));
+        }
+    }
+    text.trim_end().to_string() + "\n"
+}
+
+/// CSS to inline into the HTML of issues.
+///
+/// Only plain rules are supported: a list of selectors and their declarations.
+/// Rules apply in the order they are written, later ones winning, regardless of
+/// how specific their selectors are. `@media` queries and the like are rejected,
+/// email clients don't support them inline anyway.
+#[derive(Debug, Default)]
+pub struct Stylesheet {
+    rules: Vec<(Selector, String)>,
+}
+
+impl Stylesheet {
+    pub fn parse(css: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        let mut rest = strip_comments(css);
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .map(|close| open + close)
+                .ok_or("A `{` is never closed")?;
+            let selectors = &rest[..open];
+            let declarations = rest[open + 1..close].trim().trim_end_matches(';').trim();
+            for selector in selectors.split(',').map(str::trim) {
+                let parsed = selector.parse::<Selector>().map_err(|err| {
+                    format!("`{}` is not a supported selector: {}", selector, err)
+                })?;
+                if !declarations.is_empty() {
+                    rules.push((parsed, declarations.to_string()));
+                }
+            }
+            rest = rest[close + 1..].to_string();
+        }
+        if !rest.trim().is_empty() {
+            return Err(format!("`{}` is not inside a rule", rest.trim()));
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Moves the rules into the `style` attributes of the elements in `html` they
+    /// match.
+    fn inline(&self, html: &str) -> Result<String, lol_html::errors::RewritingError> {
+        if self.rules.is_empty() {
+            return Ok(html.to_string());
+        }
+
+        let handlers = self
+            .rules
+            .iter()
+            .map(|(selector, declarations)| {
+                // Handlers run in the order they are registered, so appending
+                // keeps later rules winning
+                let handler = ElementContentHandlers::default().element(move |el| {
+                    let style = match el.get_attribute("style") {
+                        Some(style) => format!("{}; {}", style, declarations),
+                        None => declarations.clone(),
+                    };
+                    el.set_attribute("style", &style)?;
+                    Ok(())
+                });
+                (Cow::Borrowed(selector), handler)
+            })
+            .collect();
+
+        rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers: handlers,
+                ..RewriteStrSettings::default()
+            },
+        )
+    }
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_err;
+
+    use super::{markdown_to_text, IssueContent, Stylesheet};
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let content =
+            IssueContent::from_markdown("# News\n\nHello *world*", &Stylesheet::default()).unwrap();
+
+        assert_eq!(content.html, "<h1>News</h1>\n<p>Hello <em>world</em></p>\n");
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let markdown = "Hi <script>alert(1)</script><img src=\"x.png\" onerror=\"alert(2)\">\n\n\
+            [click](javascript:alert(3))";
+
+        let content = IssueContent::from_markdown(markdown, &Stylesheet::default()).unwrap();
+
+        assert!(!content.html.contains("script"));
+        assert!(!content.html.contains("onerror"));
+        assert!(!content.html.contains("javascript:"));
+    }
+
+    #[test]
+    fn links_are_footnoted_in_the_text_version() {
+        let markdown = "Read [the post](https://example.com/post) and \
+            [the docs](https://example.com/docs), or [the post](https://example.com/post) \
+            again. Or visit <https://example.com>.";
+
+        let text = markdown_to_text(markdown);
+
+        assert_eq!(
+            text,
+            "Read the post [1] and the docs [2], or the post [1] again. Or visit \
+            https://example.com.\n\n\
+            [1] https://example.com/post\n\
+            [2] https://example.com/docs\n"
+        );
+    }
+
+    #[test]
+    fn the_text_version_keeps_the_structure_readable() {
+        let markdown = "# Title\n\nSome **bold** text.\n\n- one\n- two\n  1. nested\n\nThe end";
+
+        let text = markdown_to_text(markdown);
+
+        assert_eq!(
+            text,
+            "Title\n\nSome bold text.\n\n- one\n- two\n  1. nested\n\nThe end\n"
+        );
+    }
+
+    #[test]
+    fn merge_fields_survive_rendering() {
+        let markdown = "Hi {{ name | default: \"friend\" }}\n\n\
+            [Unsubscribe]({{unsubscribe_link}})";
+
+        let content = IssueContent::from_markdown(markdown, &Stylesheet::default()).unwrap();
+
+        assert!(content
+            .html
+            .contains(r#"Hi {{ name | default: "friend" }}"#));
+        assert!(content.html.contains(r#"href="{{unsubscribe_link}}""#));
+        assert!(content
+            .text
+            .contains(r#"Hi {{ name | default: "friend" }}"#));
+        assert!(content.text.contains("[1] {{unsubscribe_link}}"));
+    }
+
+    #[test]
+    fn css_is_inlined_in_rule_order() {
+        let stylesheet = Stylesheet::parse(
+            "/* Brand colors */\n\
+            p, li { color: #333; }\n\
+            a { color: teal; }\n\
+            p a { font-weight: bold }",
+        )
+        .unwrap();
+
+        let content =
+            IssueContent::from_markdown("Visit [us](https://example.com)", &stylesheet).unwrap();
+
+        assert_eq!(
+            content.html,
+            "<p style=\"color: #333\">Visit <a href=\"https://example.com\" \
+            rel=\"noopener noreferrer\" style=\"color: teal; font-weight: bold\">us</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn unsupported_css_is_rejected() {
+        assert_err!(Stylesheet::parse(
+            "@media (max-width: 600px) { p { color: red } }"
+        ));
+        assert_err!(Stylesheet::parse("p { color: red"));
+        assert_err!(Stylesheet::parse("p { color: red } stray"));
+    }
+
+    #[test]
+    fn parts_can_be_written_by_hand() {
+        let content = IssueContent::from_markdown("Hello", &Stylesheet::default())
+            .unwrap()
+            .with_text("Hello by hand");
+
+        assert_eq!(content.html, "<p>Hello</p>\n");
+        assert_eq!(content.text, "Hello by hand");
+    }
+}
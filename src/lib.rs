@@ -1,6 +1,22 @@
+pub mod bot_check;
+pub mod circuit_breaker;
 pub mod configuration;
+pub mod cors;
+pub mod csrf;
+pub mod dkim;
 pub mod domain;
 pub mod email_client;
+pub mod email_log;
+pub mod email_message;
+pub mod flash;
+pub mod issue_content;
+pub mod outbox;
+pub mod rate_limiter;
+pub mod request_limiter;
 pub mod routes;
+pub mod signing;
 pub mod startup;
+pub mod suppression;
 pub mod telemetry;
+pub mod templates;
+pub mod tracking;
@@ -1,4 +1,8 @@
+use std::fmt::{Debug, Display};
+
+use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
+use zero2prod::outbox::run_relay_until_stopped;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -8,8 +12,39 @@ async fn main() -> std::io::Result<()> {
     init_subscriber(subscriber);
 
     let configuration = get_configuration().expect("Failed to read configuration");
-    let app = Application::build(configuration).await?;
-    app.run_until_stopped().await?;
+    let app = Application::build(configuration.clone()).await?;
+    let relay_task = tokio::spawn(run_relay_until_stopped(configuration, app.email_client()));
+    let app_task = tokio::spawn(app.run_until_stopped());
+
+    // Whichever finishes first, for whatever reason, takes the process down
+    tokio::select! {
+        outcome = app_task => report_exit("API", outcome),
+        outcome = relay_task => report_exit("Outbox relay", outcome),
+    };
 
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
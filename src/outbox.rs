@@ -0,0 +1,310 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    email_log::{log_email, EmailPurpose},
+    email_message::EmailMessage,
+    startup::get_connection_pool,
+    suppression::is_suppressed,
+};
+
+/// An email waiting in the `outbox` table.
+pub struct OutboxEmail {
+    pub recipient: String,
+    /// `None` for recipients we don't know as subscribers
+    pub subscriber_id: Option<Uuid>,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub purpose: EmailPurpose,
+    /// See `EmailMessage::tag`
+    pub tag: Option<String>,
+    /// See `EmailMessage::message_stream`
+    pub message_stream: Option<String>,
+}
+
+/// How long a relay has to send an email it claimed. Past that, the email is due
+/// again, in case the relay died while sending it.
+const CLAIM_DURATION: Duration = Duration::from_secs(15 * 60);
+
+/// Stores `email` in the outbox, for the relay to send.
+///
+/// Pass the transaction that writes the data the email is about, so we never end
+/// up with one without the other.
+#[tracing::instrument(name = "Adding email to the outbox", skip(executor, email))]
+pub async fn enqueue_email(
+    executor: impl Executor<'_, Database = Postgres>,
+    email: &OutboxEmail,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"INSERT INTO outbox (id, recipient, subscriber_id, subject, html_body, text_body,
+            purpose, issue_id, tag, message_stream, created_at, next_attempt_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"#,
+        Uuid::new_v4(),
+        email.recipient,
+        email.subscriber_id,
+        email.subject,
+        email.html_body,
+        email.text_body,
+        email.purpose.as_str(),
+        email.purpose.issue_id(),
+        email.tag,
+        email.message_stream,
+        now,
+        now
+    )
+    .execute(executor)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+/// What happened on one pass of the relay.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    /// An email was sent, or given up on
+    TaskCompleted,
+    /// Nothing is due
+    EmptyQueue,
+    /// The provider is unavailable, the email is scheduled for another attempt
+    ProviderUnavailable,
+}
+
+/// Tries to send the outbox email that has been due for the longest.
+///
+/// The email is claimed before it is sent, so other relays skip it without
+/// waiting on the provider: it isn't due again for `CLAIM_DURATION`.
+///
+/// Sent emails are removed from the outbox. Transient failures schedule another
+/// attempt, with exponential backoff. Emails the provider rejects for good stay in
+/// the outbox, marked as failed. Emails to suppressed addresses are dropped unsent.
+#[tracing::instrument(
+    name = "Relaying an outbox email",
+    skip_all,
+    fields(outbox_email_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_relay_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let row = sqlx::query!(
+        r#"SELECT o.id, o.recipient, o.subscriber_id, o.subject, o.html_body, o.text_body,
+            o.purpose, o.issue_id, o.tag, o.message_stream, o.attempts,
+            s.suppressed_at AS "suppressed_at?"
+        FROM outbox o
+        LEFT JOIN subscriptions s ON s.id = o.subscriber_id
+        WHERE o.failed_at IS NULL AND o.next_attempt_at <= now()
+        ORDER BY o.next_attempt_at
+        FOR UPDATE OF o SKIP LOCKED
+        LIMIT 1"#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    tracing::Span::current().record("outbox_email_id", tracing::field::display(row.id));
+
+    let recipient = match SubscriberEmail::parse(row.recipient.clone()) {
+        Ok(recipient) => recipient,
+        Err(err) => {
+            tracing::error!(error = %err, "Outbox email has an invalid recipient");
+            mark_failed(&mut *transaction, row.id, &err).await?;
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    let is_suppressed = match row.subscriber_id {
+        Some(_) => row.suppressed_at.is_some(),
+        None => is_suppressed(&mut *transaction, &recipient).await?,
+    };
+    if is_suppressed {
+        tracing::info!("Dropping outbox email to a suppressed address");
+        delete_email(&mut *transaction, row.id).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    claim_email(&mut *transaction, row.id).await?;
+    transaction.commit().await?;
+
+    let mut message = EmailMessage::new(recipient, row.subject, row.html_body, row.text_body);
+    if let Some(tag) = row.tag {
+        message = message.tag(tag);
+    }
+    if let Some(message_stream) = row.message_stream {
+        message = message.message_stream(message_stream);
+    }
+    let outcome = email_client.send_email(&message).await;
+    match EmailPurpose::from_columns(&row.purpose, row.issue_id) {
+        Ok(purpose) => {
+            // The log is for looking into problems, it's not worth failing the relay over
+            let _ = log_email(pool, &row.recipient, row.subscriber_id, purpose, &outcome).await;
+        }
+        Err(err) => tracing::error!(error = %err, "Outbox email has an invalid purpose"),
+    }
+
+    let execution_outcome = match outcome {
+        Ok(delivery) => {
+            tracing::info!(provider = %delivery.provider, "Outbox email sent");
+            delete_email(pool, row.id).await?;
+            ExecutionOutcome::TaskCompleted
+        }
+        Err(err) if err.is_transient() => {
+            let delay = retry_delay(row.attempts);
+            tracing::warn!(
+                error.cause_chain = ?err,
+                attempts = row.attempts + 1,
+                retry_in_s = delay.as_secs(),
+                "Failed to send outbox email, will try again"
+            );
+            schedule_retry(pool, row.id, delay, &err.to_string()).await?;
+            ExecutionOutcome::ProviderUnavailable
+        }
+        Err(err) => {
+            tracing::error!(
+                error.cause_chain = ?err,
+                "Outbox email was rejected, giving up on it"
+            );
+            mark_failed(pool, row.id, &err.to_string()).await?;
+            ExecutionOutcome::TaskCompleted
+        }
+    };
+
+    Ok(execution_outcome)
+}
+
+/// How long to wait before the next attempt at an email that already failed
+/// `attempts` times.
+fn retry_delay(attempts: i32) -> Duration {
+    const BASE_DELAY: Duration = Duration::from_secs(30);
+    const MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+    BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempts.max(0) as u32))
+        .min(MAX_DELAY)
+}
+
+/// Keeps other relays off the email with `id` for `CLAIM_DURATION`.
+async fn claim_email(
+    executor: impl Executor<'_, Database = Postgres>,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let claimed_until = Utc::now() + chrono::Duration::from_std(CLAIM_DURATION).unwrap();
+    sqlx::query!(
+        "UPDATE outbox SET next_attempt_at = $1 WHERE id = $2",
+        claimed_until,
+        id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+async fn delete_email(
+    executor: impl Executor<'_, Database = Postgres>,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM outbox WHERE id = $1", id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+async fn schedule_retry(
+    executor: impl Executor<'_, Database = Postgres>,
+    id: Uuid,
+    delay: Duration,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let next_attempt_at = Utc::now() + chrono::Duration::from_std(delay).unwrap();
+    sqlx::query!(
+        r#"UPDATE outbox
+        SET attempts = attempts + 1, next_attempt_at = $1, last_error = $2
+        WHERE id = $3"#,
+        next_attempt_at,
+        error,
+        id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+async fn mark_failed(
+    executor: impl Executor<'_, Database = Postgres>,
+    id: Uuid,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE outbox
+        SET attempts = attempts + 1, failed_at = $1, last_error = $2
+        WHERE id = $3"#,
+        Utc::now(),
+        error,
+        id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Relays outbox emails until the process stops. Runs alongside the `Application`,
+/// configured through the same `settings`, and sharing its `email_client`.
+pub async fn run_relay_until_stopped(
+    settings: Settings,
+    email_client: Arc<EmailClient>,
+) -> Result<(), std::io::Error> {
+    let pool = get_connection_pool(&settings.database);
+    relay_loop(pool, email_client).await
+}
+
+async fn relay_loop(pool: PgPool, email_client: Arc<EmailClient>) -> Result<(), std::io::Error> {
+    loop {
+        match try_relay_email(&pool, &email_client).await {
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            // Keep new signups waiting for their confirmation email only briefly
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::ProviderUnavailable) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_delay;
+
+    #[test]
+    fn retry_delay_doubles_with_every_attempt() {
+        assert_eq!(retry_delay(0), Duration::from_secs(30));
+        assert_eq!(retry_delay(1), Duration::from_secs(60));
+        assert_eq!(retry_delay(2), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn retry_delay_is_capped_at_an_hour() {
+        assert_eq!(retry_delay(10), Duration::from_secs(60 * 60));
+        assert_eq!(retry_delay(i32::MAX), Duration::from_secs(60 * 60));
+    }
+}
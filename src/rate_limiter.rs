@@ -0,0 +1,219 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+/// At most `messages` per `per`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub messages: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub fn per_second(messages: u32) -> Self {
+        Self {
+            messages,
+            per: Duration::from_secs(1),
+        }
+    }
+
+    pub fn per_minute(messages: u32) -> Self {
+        Self {
+            messages,
+            per: Duration::from_secs(60),
+        }
+    }
+
+    fn per_second_f64(&self) -> f64 {
+        self.messages as f64 / self.per.as_secs_f64()
+    }
+}
+
+/// Spaces out the emails we send, so neither our provider nor the receiving mail
+/// servers throttle us.
+///
+/// Every email takes a token from the global bucket, and from the bucket of its
+/// recipient's domain if that domain has a cap. Buckets hold one second's worth of
+/// tokens, so sends are spread evenly instead of going out in bursts.
+pub struct SendRateLimiter {
+    global: Option<Mutex<TokenBucket>>,
+    /// Keyed by lowercase domain
+    domains: HashMap<String, Mutex<TokenBucket>>,
+}
+
+impl SendRateLimiter {
+    /// A limiter allowing `global` emails overall, and the given rate for each of the
+    /// domains in `domains`. Domains without a cap only count against `global`.
+    pub fn new(global: Option<RateLimit>, domains: HashMap<String, RateLimit>) -> Self {
+        Self {
+            global: global.map(|limit| Mutex::new(TokenBucket::new(limit))),
+            domains: domains
+                .into_iter()
+                .map(|(domain, limit)| (domain.to_lowercase(), Mutex::new(TokenBucket::new(limit))))
+                .collect(),
+        }
+    }
+
+    /// A limiter that never makes anyone wait.
+    pub fn unlimited() -> Self {
+        Self::new(None, HashMap::new())
+    }
+
+    /// Waits until we may send an email to an address at `domain`, and takes the
+    /// tokens for it. Returns how long we waited.
+    #[tracing::instrument(
+        name = "Waiting for the send rate limiter",
+        skip(self),
+        fields(throttled_ms = tracing::field::Empty)
+    )]
+    pub async fn acquire(&self, domain: &str) -> Duration {
+        let domain_bucket = self.domains.get(&domain.to_lowercase());
+        let start = Instant::now();
+
+        loop {
+            let wait = {
+                let now = Instant::now();
+                // Always lock in the same order, global first
+                let mut global = self.global.as_ref().map(|bucket| bucket.lock().unwrap());
+                let mut domain = domain_bucket.map(|bucket| bucket.lock().unwrap());
+                let wait = global
+                    .iter_mut()
+                    .chain(domain.iter_mut())
+                    .map(|bucket| bucket.wait_time(now))
+                    .max()
+                    .unwrap_or(Duration::ZERO);
+
+                // Only take tokens once every bucket has one, so we never hold on
+                // to a token while waiting for another
+                if wait.is_zero() {
+                    global
+                        .iter_mut()
+                        .chain(domain.iter_mut())
+                        .for_each(|bucket| bucket.take());
+                }
+                wait
+            };
+
+            if wait.is_zero() {
+                break;
+            }
+            tokio::time::sleep(wait).await;
+        }
+
+        let waited = start.elapsed();
+        if !waited.is_zero() {
+            tracing::Span::current().record("throttled_ms", waited.as_millis() as u64);
+            tracing::debug!("Email send was throttled");
+        }
+        waited
+    }
+}
+
+struct TokenBucket {
+    capacity: f64,
+    /// Tokens added per second
+    refill_rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket for `limit`.
+    fn new(limit: RateLimit) -> Self {
+        let refill_rate = limit.per_second_f64();
+        let capacity = refill_rate.max(1.0);
+
+        Self {
+            capacity,
+            refill_rate,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Refills the bucket up to `now`, then returns how long until it holds a whole
+    /// token. Zero if it already does.
+    fn wait_time(&mut self, now: Instant) -> Duration {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if self.refill_rate <= 0.0 {
+            // A limit of zero messages. Check back once in a while, in case that's
+            // a mistake someone fixes by restarting us.
+            Duration::from_secs(60)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.refill_rate)
+        }
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::{RateLimit, SendRateLimiter};
+
+    #[tokio::test(start_paused = true)]
+    async fn an_unlimited_limiter_never_waits() {
+        let limiter = SendRateLimiter::unlimited();
+
+        for _ in 0..1000 {
+            assert_eq!(limiter.acquire("gmail.com").await, Duration::ZERO);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_second_worth_of_sends_goes_out_right_away() {
+        let limiter = SendRateLimiter::new(Some(RateLimit::per_second(10)), HashMap::new());
+
+        for _ in 0..10 {
+            assert_eq!(limiter.acquire("gmail.com").await, Duration::ZERO);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_past_the_global_limit_are_spaced_out() {
+        let limiter = SendRateLimiter::new(Some(RateLimit::per_second(10)), HashMap::new());
+        for _ in 0..10 {
+            limiter.acquire("gmail.com").await;
+        }
+
+        let waited = limiter.acquire("example.com").await;
+
+        assert_eq!(waited, Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn domain_caps_only_apply_to_their_domain() {
+        let domains = HashMap::from([("gmail.com".to_string(), RateLimit::per_minute(6))]);
+        let limiter = SendRateLimiter::new(None, domains);
+        limiter.acquire("gmail.com").await;
+
+        let other_domain = limiter.acquire("example.com").await;
+        let same_domain = limiter.acquire("GMAIL.com").await;
+
+        assert_eq!(other_domain, Duration::ZERO);
+        assert_eq!(same_domain, Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unused_capacity_does_not_pile_up() {
+        let limiter = SendRateLimiter::new(Some(RateLimit::per_second(2)), HashMap::new());
+
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        limiter.acquire("gmail.com").await;
+        limiter.acquire("gmail.com").await;
+        let waited = limiter.acquire("gmail.com").await;
+
+        assert_eq!(waited, Duration::from_millis(500));
+    }
+}
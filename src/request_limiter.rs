@@ -0,0 +1,566 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use actix_web::{
+    http::header::{self, CacheControl, CacheDirective},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::domain::SubscriberEmail;
+
+/// At most `requests` per `window`. Windows are fixed: they start at multiples of
+/// their length since the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimit {
+    pub requests: u32,
+    pub window: Duration,
+}
+
+impl RequestLimit {
+    /// The start and end of the window `now` is in
+    fn window_at(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let length = self.window.as_secs().max(1) as i64;
+        let start = now.timestamp().div_euclid(length) * length;
+        (
+            Utc.timestamp_opt(start, 0).unwrap(),
+            Utc.timestamp_opt(start + length, 0).unwrap(),
+        )
+    }
+}
+
+/// The endpoints we limit requests to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitedEndpoint {
+    /// Both the form and the JSON API. Each subscription costs us an email.
+    Subscribe,
+    Confirm,
+}
+
+impl LimitedEndpoint {
+    pub const ALL: [Self; 2] = [Self::Subscribe, Self::Confirm];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Subscribe => "subscribe",
+            Self::Confirm => "confirm",
+        }
+    }
+}
+
+/// What requests are counted together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitScope {
+    /// Requests from the same client IP
+    Ip,
+    /// Requests for the same email address, from anywhere
+    Email,
+    /// All requests to the endpoint
+    Global,
+}
+
+impl LimitScope {
+    pub const ALL: [Self; 3] = [Self::Ip, Self::Email, Self::Global];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::Email => "email",
+            Self::Global => "global",
+        }
+    }
+}
+
+/// The limits of one endpoint. Scopes without a limit aren't counted.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointLimits {
+    pub per_ip: Option<RequestLimit>,
+    pub per_email: Option<RequestLimit>,
+    pub global: Option<RequestLimit>,
+}
+
+/// Where request counts are kept.
+pub enum LimitStore {
+    /// In the memory of this instance. Each instance counts on its own.
+    Memory(Mutex<HashMap<String, WindowCount>>),
+    /// Shared by every instance using the database
+    Postgres(PgPool),
+}
+
+/// The requests counted in the window starting at `start`
+pub struct WindowCount {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    hits: u32,
+}
+
+/// Past this many counters, the memory store drops the ones of finished windows
+const MEMORY_STORE_PRUNE_THRESHOLD: usize = 10_000;
+
+impl LimitStore {
+    pub fn memory() -> Self {
+        Self::Memory(Mutex::new(HashMap::new()))
+    }
+
+    /// Counts a request against `key`, returning the number of requests in the
+    /// current window so far, this one included.
+    async fn hit(
+        &self,
+        key: &str,
+        limit: &RequestLimit,
+        now: DateTime<Utc>,
+    ) -> Result<u32, sqlx::Error> {
+        let (start, end) = limit.window_at(now);
+        match self {
+            Self::Memory(counts) => {
+                let mut counts = counts.lock().unwrap();
+                if counts.len() > MEMORY_STORE_PRUNE_THRESHOLD {
+                    counts.retain(|_, count| count.end > now);
+                }
+                let count = counts.entry(key.to_string()).or_insert(WindowCount {
+                    start,
+                    end,
+                    hits: 0,
+                });
+                if count.start != start {
+                    *count = WindowCount {
+                        start,
+                        end,
+                        hits: 0,
+                    };
+                }
+                count.hits += 1;
+                Ok(count.hits)
+            }
+            Self::Postgres(pool) => {
+                // Now and then, clear out counters of windows long gone
+                if rand::thread_rng().gen_ratio(1, 100) {
+                    delete_finished_windows(pool, now).await?;
+                }
+                increment_window(pool, key, start, end).await
+            }
+        }
+    }
+}
+
+#[tracing::instrument(name = "Counting a request", skip(pool))]
+async fn increment_window(
+    pool: &PgPool,
+    key: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<u32, sqlx::Error> {
+    let hits = sqlx::query_scalar!(
+        r#"INSERT INTO request_counts (key, window_start, window_end, hits)
+        VALUES ($1, $2, $3, 1)
+        ON CONFLICT (key, window_start) DO UPDATE SET hits = request_counts.hits + 1
+        RETURNING hits"#,
+        key,
+        start,
+        end
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(hits.max(0) as u32)
+}
+
+#[tracing::instrument(name = "Deleting finished request counts", skip(pool))]
+async fn delete_finished_windows(pool: &PgPool, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM request_counts WHERE window_end < $1", now)
+        .execute(pool)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute query: {:?}", err);
+            err
+        })?;
+
+    Ok(())
+}
+
+/// A request went over a limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    pub scope: LimitScope,
+    /// Until the window of the limit ends
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// A 429, telling the client when to try again.
+    pub fn response(&self) -> HttpResponse {
+        // Round up, so clients retrying right on time aren't early
+        let seconds = self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0);
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, seconds.max(1)))
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .finish()
+    }
+}
+
+/// Limits how often clients may call the endpoints that cost us, so bots can't
+/// use us to flood the inboxes of others, or run up our email bill.
+///
+/// Every request counts against each limit of its endpoint, per client IP, per
+/// email address and overall, until the window of the limit ends.
+pub struct RequestLimiter {
+    store: LimitStore,
+    limits: HashMap<LimitedEndpoint, EndpointLimits>,
+    /// How many proxies in front of us append to `X-Forwarded-For`. The client IP
+    /// is the entry the outermost of them added, entries before it are whatever
+    /// the client sent. With none, it's the address of the connection.
+    trusted_proxies: usize,
+    /// How many requests went over each limit, since we started
+    hits: HashMap<(LimitedEndpoint, LimitScope), AtomicU64>,
+}
+
+impl RequestLimiter {
+    pub fn new(
+        store: LimitStore,
+        limits: HashMap<LimitedEndpoint, EndpointLimits>,
+        trusted_proxies: usize,
+    ) -> Self {
+        let hits = LimitedEndpoint::ALL
+            .into_iter()
+            .flat_map(|endpoint| {
+                LimitScope::ALL.map(|scope| ((endpoint, scope), AtomicU64::new(0)))
+            })
+            .collect();
+
+        Self {
+            store,
+            limits,
+            trusted_proxies,
+            hits,
+        }
+    }
+
+    /// Counts a request to `endpoint` from the client of `request`, about `email`
+    /// if it concerns one. `Err` if the request is over a limit.
+    ///
+    /// The store failing shouldn't take the endpoints down with it, so requests
+    /// are let through if it does.
+    pub async fn check(
+        &self,
+        endpoint: LimitedEndpoint,
+        request: &HttpRequest,
+        email: Option<&str>,
+    ) -> Result<(), RateLimited> {
+        let ip = self.client_ip(request).map(|ip| ip.to_string());
+        self.check_at(endpoint, ip.as_deref(), email, Utc::now())
+            .await
+    }
+
+    async fn check_at(
+        &self,
+        endpoint: LimitedEndpoint,
+        ip: Option<&str>,
+        email: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<(), RateLimited> {
+        let limits = match self.limits.get(&endpoint) {
+            Some(limits) => limits,
+            None => return Ok(()),
+        };
+        // Every spelling of a mailbox counts towards its limit. Emails are hashed,
+        // there's no need to keep addresses in the counters.
+        let email = email.map(|email| {
+            let email = SubscriberEmail::parse(email.trim().to_string())
+                .map(|email| email.canonical())
+                .unwrap_or_else(|_| email.trim().to_lowercase());
+            let digest = Sha256::digest(email.as_bytes());
+            digest
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        });
+        let keys = [
+            (LimitScope::Global, limits.global, Some("all".to_string())),
+            (LimitScope::Ip, limits.per_ip, ip.map(str::to_string)),
+            (LimitScope::Email, limits.per_email, email),
+        ];
+
+        let mut limited: Option<RateLimited> = None;
+        for (scope, limit, key) in keys {
+            let (limit, key) = match (limit, key) {
+                (Some(limit), Some(key)) => (limit, key),
+                _ => continue,
+            };
+            let key = format!("{}:{}:{}", endpoint.as_str(), scope.as_str(), key);
+            let hits = match self.store.hit(&key, &limit, now).await {
+                Ok(hits) => hits,
+                Err(_) => continue,
+            };
+            if hits <= limit.requests {
+                continue;
+            }
+
+            let (_, end) = limit.window_at(now);
+            let retry_after = (end - now).to_std().unwrap_or_default();
+            let total = self.hits[&(endpoint, scope)].fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::warn!(
+                endpoint = endpoint.as_str(),
+                scope = scope.as_str(),
+                client_ip = ?ip,
+                retry_after_seconds = retry_after.as_secs(),
+                total_hits = total,
+                "Request rate limit hit"
+            );
+            // When over several limits, wait for the one lasting longest
+            if limited
+                .as_ref()
+                .is_none_or(|limited| limited.retry_after < retry_after)
+            {
+                limited = Some(RateLimited { scope, retry_after });
+            }
+        }
+
+        match limited {
+            Some(limited) => Err(limited),
+            None => Ok(()),
+        }
+    }
+
+    /// How many requests to `endpoint` went over its `scope` limit since we started.
+    pub fn hits(&self, endpoint: LimitedEndpoint, scope: LimitScope) -> u64 {
+        self.hits[&(endpoint, scope)].load(Ordering::Relaxed)
+    }
+
+    /// The IP of the client of `request`, as far as we can trust it.
+    fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let peer_ip = request.peer_addr().map(|address| address.ip());
+        if self.trusted_proxies == 0 {
+            return peer_ip;
+        }
+
+        let forwarded: Vec<&str> = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        forwarded
+            .len()
+            .checked_sub(self.trusted_proxies)
+            .and_then(|index| parse_ip(forwarded[index]))
+            .or(peer_ip)
+    }
+}
+
+/// Parses an `X-Forwarded-For` entry, an IP with or without a port.
+fn parse_ip(entry: &str) -> Option<IpAddr> {
+    entry
+        .parse::<IpAddr>()
+        .or_else(|_| entry.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+    use actix_web::test::TestRequest;
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok};
+
+    use super::{
+        EndpointLimits, LimitScope, LimitStore, LimitedEndpoint, RateLimited, RequestLimit,
+        RequestLimiter,
+    };
+
+    fn limiter(limits: EndpointLimits) -> RequestLimiter {
+        RequestLimiter::new(
+            LimitStore::memory(),
+            HashMap::from([(LimitedEndpoint::Subscribe, limits)]),
+            0,
+        )
+    }
+
+    fn per_hour(requests: u32) -> Option<RequestLimit> {
+        Some(RequestLimit {
+            requests,
+            window: Duration::from_secs(3600),
+        })
+    }
+
+    #[tokio::test]
+    async fn requests_within_the_limit_pass() {
+        let limiter = limiter(EndpointLimits {
+            per_ip: per_hour(3),
+            ..EndpointLimits::default()
+        });
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+
+        for _ in 0..3 {
+            assert_ok!(
+                limiter
+                    .check_at(LimitedEndpoint::Subscribe, Some("1.2.3.4"), None, now)
+                    .await
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_limit_wait_for_the_next_window() {
+        let limiter = limiter(EndpointLimits {
+            per_ip: per_hour(1),
+            ..EndpointLimits::default()
+        });
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 12, 45, 0).unwrap();
+        let check = |now, ip| limiter.check_at(LimitedEndpoint::Subscribe, Some(ip), None, now);
+
+        assert_ok!(check(now, "1.2.3.4").await);
+        assert_eq!(
+            check(now, "1.2.3.4").await,
+            Err(RateLimited {
+                scope: LimitScope::Ip,
+                retry_after: Duration::from_secs(15 * 60)
+            })
+        );
+        assert_ok!(check(now, "5.6.7.8").await);
+        assert_ok!(check(now + chrono::Duration::minutes(15), "1.2.3.4").await);
+        assert_eq!(limiter.hits(LimitedEndpoint::Subscribe, LimitScope::Ip), 1);
+    }
+
+    #[tokio::test]
+    async fn email_limits_ignore_case_and_where_requests_come_from() {
+        let limiter = limiter(EndpointLimits {
+            per_email: per_hour(1),
+            ..EndpointLimits::default()
+        });
+        let now = Utc::now();
+
+        assert_ok!(
+            limiter
+                .check_at(
+                    LimitedEndpoint::Subscribe,
+                    Some("1.2.3.4"),
+                    Some("victim@example.com"),
+                    now
+                )
+                .await
+        );
+        let result = limiter
+            .check_at(
+                LimitedEndpoint::Subscribe,
+                Some("5.6.7.8"),
+                Some("Victim@Example.com "),
+                now,
+            )
+            .await;
+
+        assert_eq!(result.unwrap_err().scope, LimitScope::Email);
+    }
+
+    #[tokio::test]
+    async fn email_limits_count_every_spelling_of_a_mailbox() {
+        let limiter = limiter(EndpointLimits {
+            per_email: per_hour(1),
+            ..EndpointLimits::default()
+        });
+        let now = Utc::now();
+        let check = |email| limiter.check_at(LimitedEndpoint::Subscribe, None, Some(email), now);
+
+        assert_ok!(check("ursula.le.guin@gmail.com").await);
+        let result = check("UrsulaLeGuin+news@googlemail.com").await;
+
+        assert_eq!(result.unwrap_err().scope, LimitScope::Email);
+    }
+
+    #[tokio::test]
+    async fn the_global_limit_counts_every_request() {
+        let limiter = limiter(EndpointLimits {
+            global: per_hour(2),
+            ..EndpointLimits::default()
+        });
+        let now = Utc::now();
+
+        for ip in ["1.1.1.1", "2.2.2.2"] {
+            assert_ok!(
+                limiter
+                    .check_at(LimitedEndpoint::Subscribe, Some(ip), None, now)
+                    .await
+            );
+        }
+        assert_err!(
+            limiter
+                .check_at(LimitedEndpoint::Subscribe, Some("3.3.3.3"), None, now)
+                .await
+        );
+        assert_eq!(
+            limiter.hits(LimitedEndpoint::Subscribe, LimitScope::Global),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn endpoints_without_limits_are_not_limited() {
+        let limiter = limiter(EndpointLimits::default());
+
+        for _ in 0..100 {
+            assert_ok!(
+                limiter
+                    .check_at(LimitedEndpoint::Confirm, Some("1.2.3.4"), None, Utc::now())
+                    .await
+            );
+        }
+    }
+
+    #[test]
+    fn client_ips_come_from_the_trusted_proxy() {
+        let limiter = |trusted_proxies| {
+            RequestLimiter::new(LimitStore::memory(), HashMap::new(), trusted_proxies)
+        };
+        let request = |forwarded: &[&str]| {
+            let mut request = TestRequest::default().peer_addr("10.0.0.1:4000".parse().unwrap());
+            for value in forwarded {
+                request = request.append_header(("X-Forwarded-For", *value));
+            }
+            request.to_http_request()
+        };
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+
+        // Whatever clients put first is ignored
+        let spoofed = request(&["6.6.6.6, 7.7.7.7", "1.2.3.4"]);
+        assert_eq!(limiter(1).client_ip(&spoofed), ip("1.2.3.4"));
+        assert_eq!(limiter(2).client_ip(&spoofed), ip("7.7.7.7"));
+        assert_eq!(limiter(0).client_ip(&spoofed), ip("10.0.0.1"));
+
+        // Ports are dropped, and anything else falls back to the connection
+        assert_eq!(
+            limiter(1).client_ip(&request(&["[2001:db8::1]:443"])),
+            ip("2001:db8::1")
+        );
+        assert_eq!(limiter(1).client_ip(&request(&["unknown"])), ip("10.0.0.1"));
+        assert_eq!(limiter(1).client_ip(&request(&[])), ip("10.0.0.1"));
+        assert_eq!(limiter(4).client_ip(&spoofed), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn retry_after_is_rounded_up_to_whole_seconds() {
+        let limited = RateLimited {
+            scope: LimitScope::Ip,
+            retry_after: Duration::from_millis(1500),
+        };
+
+        let response = limited.response();
+
+        assert_eq!(response.status().as_u16(), 429);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "2");
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::OnceLock;
+
+use actix_web::{
+    get,
+    http::header::{self, CacheControl, CacheDirective, ContentType, ETag, EntityTag},
+    web, HttpRequest, HttpResponse,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    domain::{Locale, SubscriptionSource},
+    startup::ApplicationBaseUrl,
+};
+
+/// The signup widget partners embed in their pages
+const WIDGET: &str = include_str!("../../static/widget.js");
+
+/// Bump whenever the widget changes in a way embeds might notice. Old versioned URLs
+/// then stop working, so pages don't keep a stale copy cached forever.
+pub const WIDGET_VERSION: &str = "2";
+
+const JAVASCRIPT: &str = "application/javascript; charset=utf-8";
+
+/// How long browsers may use the unversioned widget before checking for a new one
+const UNVERSIONED_MAX_AGE_SECONDS: u32 = 60 * 60;
+
+/// Serves the latest widget. Browsers revalidate it hourly, so embeds pointing here
+/// pick up new versions on their own.
+#[tracing::instrument(name = "Serving the widget", skip(request))]
+#[get("/embed/widget.js")]
+pub async fn widget(request: HttpRequest) -> HttpResponse {
+    let etag = widget_etag();
+    let is_cached = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag.to_string()));
+    let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(UNVERSIONED_MAX_AGE_SECONDS),
+    ]);
+
+    if is_cached {
+        return HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(cache_control)
+            .finish();
+    }
+    HttpResponse::Ok()
+        .content_type(JAVASCRIPT)
+        .insert_header(ETag(etag))
+        .insert_header(cache_control)
+        .insert_header(("X-Widget-Version", WIDGET_VERSION))
+        .body(WIDGET)
+}
+
+/// Serves the widget at a URL that only ever has this version, so browsers may
+/// cache it for good.
+#[tracing::instrument(name = "Serving a versioned widget")]
+#[get("/embed/v{version}/widget.js")]
+pub async fn versioned_widget(version: web::Path<String>) -> HttpResponse {
+    if version.as_str() != WIDGET_VERSION {
+        return HttpResponse::NotFound().finish();
+    }
+
+    HttpResponse::Ok()
+        .content_type(JAVASCRIPT)
+        .insert_header(ETag(widget_etag()))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(365 * 24 * 60 * 60),
+            CacheDirective::Extension("immutable".to_string(), None),
+        ]))
+        .insert_header(("X-Widget-Version", WIDGET_VERSION))
+        .body(WIDGET)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SnippetParameters {
+    /// Recorded with every signup through the embed
+    source: Option<String>,
+    /// A language tag like `fr`. The visitor's browser language if not set.
+    locale: Option<String>,
+}
+
+/// The HTML partners paste into their pages to embed the widget.
+#[tracing::instrument(name = "Building a widget snippet", skip(base_url))]
+#[get("/embed/snippet")]
+pub async fn widget_snippet(
+    parameters: web::Query<SnippetParameters>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let parameters = parameters.into_inner();
+    let source = match parameters.source.map(SubscriptionSource::parse).transpose() {
+        Ok(source) => source,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+    let locale = match parameters.locale.as_deref().map(Locale::parse).transpose() {
+        Ok(locale) => locale,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    // Both are checked to be plain ASCII slugs, nothing to escape
+    let mut attributes = String::new();
+    if let Some(source) = source {
+        attributes.push_str(&format!(r#" data-source="{}""#, source.as_ref()));
+    }
+    if let Some(locale) = locale {
+        attributes.push_str(&format!(r#" data-locale="{}""#, locale.as_str()));
+    }
+    let snippet = format!(
+        "<div data-newsletter-signup{}></div>\n\
+        <script src=\"{}/embed/v{}/widget.js\" async></script>\n",
+        attributes, base_url.0, WIDGET_VERSION
+    );
+
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(snippet)
+}
+
+/// Identifies the content of the widget, for conditional requests
+fn widget_etag() -> EntityTag {
+    static HASH: OnceLock<String> = OnceLock::new();
+    let hash = HASH.get_or_init(|| {
+        Sha256::digest(WIDGET.as_bytes())
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    });
+    EntityTag::new_strong(format!("{}-{}", WIDGET_VERSION, hash))
+}
@@ -1,7 +1,44 @@
-use actix_web::{get, HttpResponse};
+use actix_web::{get, web, HttpResponse};
 
-/// Health check endpoint that will always respond with a 200 response
+use crate::{
+    email_client::EmailClient,
+    request_limiter::{LimitScope, LimitedEndpoint, RequestLimiter},
+};
+
+/// Health check endpoint that will always respond with a 200 response.
+///
+/// The state of each email provider's circuit breaker is reported in the
+/// `X-Email-Circuit-State` header, e.g. `postmark=closed, backup=open`. How many
+/// requests went over each request limit since the instance started is in the
+/// `X-Request-Limit-Hits` header, e.g. `subscribe.ip=3, subscribe.email=0, ...`.
 #[get("/health_check")]
-pub async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().finish()
+pub async fn health_check(
+    email_client: web::Data<EmailClient>,
+    limiter: web::Data<RequestLimiter>,
+) -> HttpResponse {
+    let circuit_states = email_client
+        .providers()
+        .iter()
+        .map(|provider| format!("{}={}", provider.name(), provider.circuit_state().as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let limit_hits = LimitedEndpoint::ALL
+        .into_iter()
+        .flat_map(|endpoint| LimitScope::ALL.map(|scope| (endpoint, scope)))
+        .map(|(endpoint, scope)| {
+            format!(
+                "{}.{}={}",
+                endpoint.as_str(),
+                scope.as_str(),
+                limiter.hits(endpoint, scope)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    HttpResponse::Ok()
+        .insert_header(("X-Email-Circuit-State", circuit_states))
+        .insert_header(("X-Request-Limit-Hits", limit_hits))
+        .finish()
 }
@@ -1,7 +1,17 @@
+mod embed;
 mod health;
+mod subscription_form;
 mod subscriptions;
 mod subscriptions_confirm;
+mod templates;
+mod tracking;
+mod webhooks;
 
+pub use embed::*;
 pub use health::*;
+pub use subscription_form::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use templates::*;
+pub use tracking::*;
+pub use webhooks::*;
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    http::header::{self, CacheControl, CacheDirective, ContentType},
+    web, HttpRequest, HttpResponse,
+};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bot_check::BotCheck,
+    csrf::CsrfToken,
+    domain::Locale,
+    flash::{flash_cookie, read_flash, removal_cookie},
+    startup::{ApplicationBaseUrl, HmacSecret},
+    templates::Templates,
+};
+
+/// What happened to the last submission of the subscription form, shown after the
+/// redirect back to it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SubscribeFlash {
+    /// A confirmation email is on its way to `email`
+    Subscribed { email: String },
+    /// The submission was rejected because of `error`. The values submitted are
+    /// put back into the form.
+    Rejected {
+        error: String,
+        name: String,
+        email: String,
+        /// The timestamp of the form submitted, see `BotCheck::carry_over`
+        rendered_at: Option<String>,
+    },
+    /// `email` looks misspelled, we asked whether `suggestion` was meant
+    Suggested {
+        suggestion: String,
+        name: String,
+        email: String,
+        /// The timestamp of the form first submitted
+        rendered_at: String,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct FormParameters {
+    /// A language tag like `fr`. Taken from the `Accept-Language` header if not set.
+    locale: Option<String>,
+}
+
+/// Serves the subscription form, or the outcome of submitting it.
+#[tracing::instrument(
+    name = "Showing the subscription form",
+    skip(request, parameters, templates, secret, base_url)
+)]
+pub async fn subscribe_form(
+    request: HttpRequest,
+    parameters: web::Query<FormParameters>,
+    templates: web::Data<Templates>,
+    secret: web::Data<HmacSecret>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let accept_language = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    let locale = Locale::negotiate(parameters.locale.as_deref(), accept_language);
+    // Reusing the token keeps forms open in other tabs working
+    let csrf_token =
+        CsrfToken::from_request(&request, &secret.0).unwrap_or_else(|_| CsrfToken::generate());
+    let flash = read_flash::<SubscribeFlash>(&request, &secret.0);
+
+    let (page, variables) = match &flash {
+        Some(SubscribeFlash::Subscribed { email }) => {
+            ("subscribed", HashMap::from([("email", email.clone())]))
+        }
+        Some(SubscribeFlash::Rejected {
+            error,
+            name,
+            email,
+            rendered_at,
+        }) => (
+            "subscribe",
+            HashMap::from([
+                ("csrf_token", csrf_token.as_str().to_owned()),
+                (
+                    "rendered_at",
+                    BotCheck::carry_over(rendered_at.as_deref(), &secret.0),
+                ),
+                ("error", error.clone()),
+                ("name", name.clone()),
+                ("email", email.clone()),
+            ]),
+        ),
+        Some(SubscribeFlash::Suggested {
+            suggestion,
+            name,
+            email,
+            rendered_at,
+        }) => (
+            "email_suggestion",
+            HashMap::from([
+                ("csrf_token", csrf_token.as_str().to_owned()),
+                ("rendered_at", rendered_at.clone()),
+                ("suggestion", suggestion.clone()),
+                ("name", name.clone()),
+                ("email", email.clone()),
+            ]),
+        ),
+        None => (
+            "subscribe",
+            HashMap::from([
+                ("csrf_token", csrf_token.as_str().to_owned()),
+                ("rendered_at", BotCheck::timestamp(&secret.0)),
+            ]),
+        ),
+    };
+    let html = match templates.render_page(page, locale, &variables) {
+        Ok(html) => html,
+        Err(err) => {
+            tracing::error!(error = %err, page, "Failed to render page");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .cookie(csrf_token.cookie(&secret.0, is_secure(&base_url)));
+    if flash.is_some() {
+        response.cookie(removal_cookie());
+    }
+    response.body(html)
+}
+
+/// Sends the browser back to the subscription form, in `locale`, to show `flash`.
+pub fn redirect_to_form(
+    flash: &SubscribeFlash,
+    locale: Locale,
+    secret: &Secret<String>,
+    base_url: &ApplicationBaseUrl,
+) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((
+            header::LOCATION,
+            format!("/subscribe?locale={}", locale.as_str()),
+        ))
+        .cookie(flash_cookie(flash, secret, is_secure(base_url)))
+        .finish()
+}
+
+/// Whether browsers reach us over HTTPS, so our cookies should never be sent in the
+/// clear.
+fn is_secure(base_url: &ApplicationBaseUrl) -> bool {
+    base_url.0.starts_with("https://")
+}
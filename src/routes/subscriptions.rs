@@ -1,14 +1,26 @@
-use actix_web::{post, web, HttpResponse};
+use std::collections::HashMap;
+
+use actix_web::{http::header::ACCEPT_LANGUAGE, web, HttpRequest, HttpResponse};
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
+use serde_json::json;
 use sqlx::{Executor, PgPool, Postgres};
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
-    startup::ApplicationBaseUrl,
+    bot_check::BotCheck,
+    csrf::CsrfToken,
+    domain::{
+        EmailPolicy, EmailRejection, EmailSuggester, Locale, NewSubscriber, SubscriberEmail,
+        SubscriberName, SubscriptionSource,
+    },
+    email_log::EmailPurpose,
+    outbox::{enqueue_email, OutboxEmail},
+    request_limiter::{LimitedEndpoint, RequestLimiter},
+    routes::{redirect_to_form, SubscribeFlash},
+    startup::{ApplicationBaseUrl, HmacSecret, LegacyFormPosts},
+    templates::{TemplateError, Templates},
 };
 
 /// The data being submitted from the subscription form
@@ -16,6 +28,17 @@ use crate::{
 pub struct FormData {
     email: String,
     name: String,
+    /// A language tag like `fr`. Taken from the `Accept-Language` header if not set.
+    locale: Option<String>,
+    /// Set by our own subscription form, see `subscribe_form`
+    csrf_token: Option<String>,
+    /// Hidden from people, only bots fill it in
+    website: Option<String>,
+    /// Signed by us when our form was rendered, to tell how long it took to submit
+    rendered_at: Option<String>,
+    /// Set when the subscriber turned down our suggestion for another address
+    #[serde(default)]
+    keep_email: bool,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -30,65 +53,394 @@ impl TryFrom<FormData> for NewSubscriber {
 }
 
 /// Adds a new subscription.
+///
+/// Submissions of our own form, which carry a CSRF token, are redirected back to
+/// it to show the outcome. Forms hosted elsewhere get a bare status code, as long
+/// as `LegacyFormPosts` are accepted.
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(
+        request,
+        form,
+        pool,
+        base_url,
+        templates,
+        secret,
+        legacy_form_posts,
+        limiter,
+        bot_check,
+        email_policy,
+        email_suggester
+    ),
     fields(
         subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_name = %form.name,
+        locale = tracing::field::Empty
     )
 )]
-#[post("/subscribe")]
+// Handlers take what they need as extractors
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    templates: web::Data<Templates>,
+    secret: web::Data<HmacSecret>,
+    legacy_form_posts: web::Data<LegacyFormPosts>,
+    limiter: web::Data<RequestLimiter>,
+    bot_check: web::Data<BotCheck>,
+    email_policy: web::Data<EmailPolicy>,
+    email_suggester: web::Data<EmailSuggester>,
 ) -> HttpResponse {
-    let new_subscriber = match form.0.try_into() {
+    if let Err(limited) = limiter
+        .check(LimitedEndpoint::Subscribe, &request, Some(&form.email))
+        .await
+    {
+        return limited.response();
+    }
+
+    let accept_language = request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    let locale = Locale::negotiate(form.locale.as_deref(), accept_language);
+    tracing::Span::current().record("locale", locale.as_str());
+
+    let from_form_page = match &form.csrf_token {
+        Some(token) => {
+            if let Err(err) = CsrfToken::verify(&request, token, &secret.0) {
+                tracing::warn!(error.cause_chain = ?err, "Rejected CSRF token");
+                let flash = SubscribeFlash::Rejected {
+                    error: "The form has expired, please submit it again.".to_string(),
+                    name: form.name.clone(),
+                    email: form.email.clone(),
+                    rendered_at: form.rendered_at.clone(),
+                };
+                return redirect_to_form(&flash, locale, &secret.0, &base_url);
+            }
+            true
+        }
+        None if legacy_form_posts.0 => false,
+        None => return HttpResponse::Forbidden().finish(),
+    };
+
+    let verdict = bot_check.check(
+        form.website.as_deref(),
+        form.rendered_at.as_deref(),
+        from_form_page,
+        &secret.0,
+    );
+    if verdict.is_bot() {
+        // Looks like it worked, so bots don't try another way in
+        tracing::info!(bot_verdict = verdict.as_str(), "Dropped a bot subscription");
+        if from_form_page {
+            let flash = SubscribeFlash::Subscribed {
+                email: form.0.email,
+            };
+            return redirect_to_form(&flash, locale, &secret.0, &base_url);
+        }
+        return HttpResponse::Ok().finish();
+    }
+
+    let (name, email) = (form.name.clone(), form.email.clone());
+    let (rendered_at, keep_email) = (form.rendered_at.clone(), form.keep_email);
+    let new_subscriber = match NewSubscriber::try_from(form.0).and_then(|subscriber| {
+        check_policy(&email_policy, &subscriber.email)
+            .map_err(|rejection| rejection.to_string())?;
+        Ok(subscriber)
+    }) {
         Ok(subscriber) => subscriber,
+        Err(error) if from_form_page => {
+            let flash = SubscribeFlash::Rejected {
+                error,
+                name,
+                email,
+                rendered_at,
+            };
+            return redirect_to_form(&flash, locale, &secret.0, &base_url);
+        }
         Err(_) => return HttpResponse::BadRequest().finish(),
     };
 
-    let mut transaction = match pool.begin().await {
-        Ok(transaction) => transaction,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    // Forms hosted elsewhere have no way to show suggestions
+    if from_form_page && !keep_email {
+        if let Some(suggestion) = suggest(&email_suggester, &new_subscriber.email) {
+            let flash = SubscribeFlash::Suggested {
+                suggestion: suggestion.as_ref().to_string(),
+                name,
+                email,
+                // Answering the suggestion doesn't make the submission any faster
+                rendered_at: rendered_at.unwrap_or_default(),
+            };
+            return redirect_to_form(&flash, locale, &secret.0, &base_url);
+        }
+    }
 
-    let subscriber_id = match insert_subscriber(&mut *transaction, &new_subscriber).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    if let Err(err) = add_subscriber(
+        &pool,
+        &templates,
+        &base_url.0,
+        &new_subscriber,
+        locale,
+        None,
+    )
+    .await
+    {
+        tracing::error!(error.cause_chain = ?err, "Failed to add subscriber");
+        return HttpResponse::InternalServerError().finish();
+    }
 
-    let subscription_token = generate_subscription_token();
-    if store_token(&mut *transaction, subscriber_id, &subscription_token)
+    if from_form_page {
+        let flash = SubscribeFlash::Subscribed { email };
+        return redirect_to_form(&flash, locale, &secret.0, &base_url);
+    }
+    HttpResponse::Ok().finish()
+}
+
+/// A subscription through the JSON API, or posted as a form by the embedded widget
+#[derive(Deserialize)]
+pub struct SubscriptionRequest {
+    email: String,
+    name: String,
+    /// A language tag like `fr`. Taken from the `Accept-Language` header if not set.
+    locale: Option<String>,
+    /// Where the subscriber signed up, e.g. the `data-source` of a widget
+    source: Option<String>,
+    /// Set when the subscriber turned down our suggestion for another address
+    #[serde(default)]
+    keep_email: bool,
+    /// Set by clients able to offer subscribers a `suggestion`, like the widget
+    /// since version 2
+    #[serde(default)]
+    suggestions: bool,
+}
+
+/// Adds a new subscription, for scripts. Responds with JSON: the status of the new
+/// subscription, or an `error` to show the subscriber.
+///
+/// Addresses that look misspelled get a 409 with a `suggestion`, if the client
+/// asked for `suggestions` and the address doesn't come with `keep_email`.
+///
+/// Only scripts on the sites CORS allows can read the response, see
+/// `create_embedded_subscription` for the widget.
+// Handlers take what they need as extractors
+#[allow(clippy::too_many_arguments)]
+pub async fn create_subscription(
+    request: HttpRequest,
+    body: web::Json<SubscriptionRequest>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    templates: web::Data<Templates>,
+    limiter: web::Data<RequestLimiter>,
+    email_policy: web::Data<EmailPolicy>,
+    email_suggester: web::Data<EmailSuggester>,
+) -> HttpResponse {
+    handle_subscription_request(
+        &request,
+        body.into_inner(),
+        &pool,
+        &base_url,
+        &templates,
+        &limiter,
+        &email_policy,
+        &email_suggester,
+    )
+    .await
+}
+
+/// Adds a new subscription from the embedded widget. Works like
+/// `create_subscription`, but takes a form post: browsers send those from any page
+/// without asking us first, in a preflight request. Scripts on any page may read
+/// the response, which is the same for everyone.
+// Handlers take what they need as extractors
+#[allow(clippy::too_many_arguments)]
+pub async fn create_embedded_subscription(
+    request: HttpRequest,
+    body: web::Form<SubscriptionRequest>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    templates: web::Data<Templates>,
+    limiter: web::Data<RequestLimiter>,
+    email_policy: web::Data<EmailPolicy>,
+    email_suggester: web::Data<EmailSuggester>,
+) -> HttpResponse {
+    handle_subscription_request(
+        &request,
+        body.into_inner(),
+        &pool,
+        &base_url,
+        &templates,
+        &limiter,
+        &email_policy,
+        &email_suggester,
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Adding a new subscriber through the API",
+    skip(
+        request,
+        body,
+        pool,
+        base_url,
+        templates,
+        limiter,
+        email_policy,
+        email_suggester
+    ),
+    fields(
+        subscriber_email = %body.email,
+        subscriber_name = %body.name,
+        source = ?body.source,
+        locale = tracing::field::Empty
+    )
+)]
+// Takes what the handlers extracted
+#[allow(clippy::too_many_arguments)]
+async fn handle_subscription_request(
+    request: &HttpRequest,
+    body: SubscriptionRequest,
+    pool: &PgPool,
+    base_url: &ApplicationBaseUrl,
+    templates: &Templates,
+    limiter: &RequestLimiter,
+    email_policy: &EmailPolicy,
+    email_suggester: &EmailSuggester,
+) -> HttpResponse {
+    if let Err(limited) = limiter
+        .check(LimitedEndpoint::Subscribe, request, Some(&body.email))
         .await
-        .is_err()
     {
-        return HttpResponse::InternalServerError().finish();
+        return limited.response();
     }
 
-    if transaction.commit().await.is_err() {
-        return HttpResponse::InternalServerError().finish();
+    let accept_language = request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    let locale = Locale::negotiate(body.locale.as_deref(), accept_language);
+    tracing::Span::current().record("locale", locale.as_str());
+
+    let source = match body.source.map(SubscriptionSource::parse).transpose() {
+        Ok(source) => source,
+        Err(error) => return HttpResponse::BadRequest().json(json!({ "error": error })),
+    };
+    let new_subscriber = match (
+        SubscriberName::parse(body.name),
+        SubscriberEmail::parse(body.email),
+    ) {
+        (Ok(name), Ok(email)) => NewSubscriber { email, name },
+        (Err(error), _) | (_, Err(error)) => {
+            return HttpResponse::BadRequest().json(json!({ "error": error }))
+        }
+    };
+    if let Err(rejection) = check_policy(email_policy, &new_subscriber.email) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": rejection.to_string(),
+            "reason": rejection.as_str(),
+        }));
+    }
+    if body.suggestions && !body.keep_email {
+        if let Some(suggestion) = suggest(email_suggester, &new_subscriber.email) {
+            return HttpResponse::Conflict().json(json!({
+                "error": format!("Did you mean {}?", suggestion.as_ref()),
+                "suggestion": suggestion.as_ref(),
+            }));
+        }
     }
 
-    if send_confirmation_email(
-        &email_client,
-        new_subscriber,
+    if let Err(err) = add_subscriber(
+        pool,
+        templates,
         &base_url.0,
-        &subscription_token,
+        &new_subscriber,
+        locale,
+        source.as_ref(),
     )
     .await
-    .is_err()
     {
-        return HttpResponse::InternalServerError().finish();
+        tracing::error!(error.cause_chain = ?err, "Failed to add subscriber");
+        return HttpResponse::InternalServerError()
+            .json(json!({ "error": "Something went wrong, please try again later." }));
     }
 
-    HttpResponse::Ok().finish()
+    HttpResponse::Ok().json(json!({ "status": "pending_confirmation" }))
+}
+
+/// Checks `email` against the policy, recording why it was turned away.
+fn check_policy(policy: &EmailPolicy, email: &SubscriberEmail) -> Result<(), EmailRejection> {
+    policy.check(email).inspect_err(|rejection| {
+        tracing::info!(reason = rejection.as_str(), "Rejected email by policy");
+    })
+}
+
+/// The address `email` was probably meant to be, recording the suggestion.
+fn suggest(suggester: &EmailSuggester, email: &SubscriberEmail) -> Option<SubscriberEmail> {
+    let suggestion = suggester.suggest(email)?;
+    tracing::info!(
+        suggestion = suggestion.as_ref(),
+        "Suggested another address"
+    );
+    Some(suggestion)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubscribeError {
+    #[error("Failed to store the subscription")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to render the confirmation email")]
+    Template(#[from] TemplateError),
+}
+
+/// Stores `new_subscriber`, pending confirmation, along with the confirmation
+/// email for the outbox relay to send. Both go in, or neither does. Subscribers
+/// still pending confirmation get a new token and email instead.
+async fn add_subscriber(
+    pool: &PgPool,
+    templates: &Templates,
+    base_url: &str,
+    new_subscriber: &NewSubscriber,
+    locale: Locale,
+    source: Option<&SubscriptionSource>,
+) -> Result<(), SubscribeError> {
+    let mut transaction = pool.begin().await?;
+    let subscriber_id =
+        match insert_subscriber(&mut *transaction, new_subscriber, locale, source).await? {
+            Some(subscriber_id) => subscriber_id,
+            // Their confirmation email may never have arrived, so they get another
+            None => match pending_subscriber_id(&mut *transaction, &new_subscriber.email).await? {
+                Some(subscriber_id) => {
+                    tracing::info!("The address is pending confirmation, sending another email");
+                    subscriber_id
+                }
+                // Answered like any other signup, so the form doesn't tell who subscribed
+                None => {
+                    tracing::info!("The address is already subscribed, under some spelling");
+                    return Ok(());
+                }
+            },
+        };
+    let subscription_token = generate_subscription_token();
+    store_token(&mut *transaction, subscriber_id, &subscription_token).await?;
+    let confirmation_email = confirmation_email(
+        templates,
+        new_subscriber,
+        locale,
+        subscriber_id,
+        base_url,
+        &subscription_token,
+    )?;
+    enqueue_email(&mut *transaction, &confirmation_email).await?;
+    transaction.commit().await?;
+
+    Ok(())
 }
 
 /// Inserts a new subscriber into the database, returning the ID of the new
-/// user if successful.
+/// user if successful. Returns `None` if the address is already subscribed, under
+/// any spelling.
 #[tracing::instrument(
     name = "Saving subscriber details in database.",
     skip(new_subscriber, transaction)
@@ -96,24 +448,49 @@ pub async fn subscribe(
 async fn insert_subscriber(
     transaction: impl Executor<'_, Database = Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
-        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')"#,
-        subscriber_id,
+    locale: Locale,
+    source: Option<&SubscriptionSource>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"INSERT INTO subscriptions
+            (id, email, canonical_email, name, subscribed_at, status, locale, source)
+        VALUES ($1, $2, $3, $4, $5, 'pending_confirmation', $6, $7)
+        ON CONFLICT DO NOTHING
+        RETURNING id"#,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
+        new_subscriber.email.canonical(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        locale.as_str(),
+        source.map(AsRef::as_ref)
     )
-    .execute(transaction)
+    .fetch_optional(transaction)
     .await
     .map_err(|err| {
         tracing::error!("Failed to execute query: {:?}", err);
         err
-    })?;
+    })
+}
 
-    Ok(subscriber_id)
+/// The ID of the subscriber with the same mailbox as `email`, if they have yet to
+/// confirm their subscription.
+#[tracing::instrument(name = "Looking up a pending subscriber", skip(transaction, email))]
+async fn pending_subscriber_id(
+    transaction: impl Executor<'_, Database = Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT id FROM subscriptions
+        WHERE canonical_email = $1 AND status = 'pending_confirmation'"#,
+        email.canonical()
+    )
+    .fetch_optional(transaction)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })
 }
 
 /// Creates a random subscription token. This is meant to be a single use token with
@@ -154,35 +531,35 @@ async fn store_token(
     Ok(())
 }
 
-/// Sends a confirmation email to a new subscriber. Uses `base_url` to build the URL
-/// for our confirmation API.
-///
-/// Returns an `Err` if there's a failure connecting to the email server.
-#[tracing::instrument(
-    name = "Sending confirmation email to new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
-)]
-async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+/// Builds the confirmation email for a new subscriber from the `confirmation`
+/// template, in their `locale`. Uses `base_url` to build the URL for our
+/// confirmation API.
+fn confirmation_email(
+    templates: &Templates,
+    new_subscriber: &NewSubscriber,
+    locale: Locale,
+    subscriber_id: Uuid,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<OutboxEmail, TemplateError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let text_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+    let variables = HashMap::from([
+        ("name", new_subscriber.name.as_ref().to_owned()),
+        ("confirmation_link", confirmation_link),
+    ]);
+    let rendered = templates.render("confirmation", locale, &variables)?;
 
-    email_client
-        .send_email(new_subscriber.email, "Welcome", &html_body, &text_body)
-        .await
+    Ok(OutboxEmail {
+        recipient: new_subscriber.email.as_ref().to_owned(),
+        subscriber_id: Some(subscriber_id),
+        subject: rendered.subject,
+        html_body: rendered.html_body,
+        text_body: rendered.text_body,
+        purpose: EmailPurpose::Confirmation,
+        tag: Some("confirmation".to_string()),
+        message_stream: None,
+    })
 }
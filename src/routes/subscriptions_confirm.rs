@@ -1,50 +1,209 @@
-use actix_web::{web, HttpResponse};
+use std::collections::HashMap;
+
+use actix_web::{
+    http::{
+        header::{ContentType, ACCEPT_LANGUAGE, LOCATION},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use url::Url;
 use uuid::Uuid;
 
+use crate::{
+    domain::Locale,
+    request_limiter::{LimitedEndpoint, RequestLimiter},
+    templates::Templates,
+};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     /// Require a token to confirm, otherwise will return an error status
     subscription_token: String,
 }
 
-/// Confirm a subscription. Afterwards, subscriber will start receiving the newsletter
-#[tracing::instrument(name = "Confirming a pending subscription", skip(parameters))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let subscriber_id =
-        match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-            Ok(id) => id,
-            Err(_) => return HttpResponse::InternalServerError().finish(),
-        };
-
-    let subscriber_id = match subscriber_id {
-        Some(id) => id,
-        None => return HttpResponse::Unauthorized().finish(),
-    };
+/// How `confirm` treats the people clicking confirmation links.
+#[derive(Debug, Clone)]
+pub struct ConfirmationOptions {
+    /// How long after signing up a confirmation link stops working
+    pub token_lifetime: chrono::Duration,
+    /// Redirect here after confirming, instead of showing our own page
+    pub success_url: Option<Url>,
+    /// Redirect here when a link doesn't work, instead of showing our own page
+    pub failure_url: Option<Url>,
+}
+
+/// Why a confirmation link didn't work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfirmationFailure {
+    /// We don't know the token
+    Invalid,
+    /// The token is older than the token lifetime
+    Expired,
+}
 
-    if set_subscriber_confirmed(&pool, subscriber_id)
+impl ConfirmationFailure {
+    /// The `reason` parameter of failure redirects
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Invalid => "invalid",
+            Self::Expired => "expired",
+        }
+    }
+
+    fn page(&self) -> &'static str {
+        match self {
+            Self::Invalid => "confirmation_invalid",
+            Self::Expired => "confirmation_expired",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Invalid => StatusCode::UNAUTHORIZED,
+            Self::Expired => StatusCode::GONE,
+        }
+    }
+}
+
+/// Confirm a subscription. Afterwards, subscriber will start receiving the newsletter
+///
+/// People get here by clicking the link in their confirmation email, so we respond
+/// with a page in their language: thanking them, or explaining why the link
+/// didn't work. Or we redirect them to the pages configured in
+/// `ConfirmationOptions`.
+#[tracing::instrument(
+    name = "Confirming a pending subscription",
+    skip(request, parameters, pool, templates, options, limiter)
+)]
+pub async fn confirm(
+    request: HttpRequest,
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<Templates>,
+    options: web::Data<ConfirmationOptions>,
+    limiter: web::Data<RequestLimiter>,
+) -> HttpResponse {
+    // Keeps bots from guessing tokens
+    if let Err(limited) = limiter
+        .check(LimitedEndpoint::Confirm, &request, None)
         .await
-        .is_err()
     {
-        return HttpResponse::InternalServerError().finish();
+        return limited.response();
+    }
+
+    let subscriber = match get_subscriber_from_token(&pool, &parameters.subscription_token).await {
+        Ok(subscriber) => subscriber,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => {
+            let locale = request
+                .headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Locale::from_accept_language)
+                .unwrap_or_default();
+            return failure_response(ConfirmationFailure::Invalid, locale, &templates, &options);
+        }
+    };
+    // Subscribers signed up with a locale we support, but it may have been dropped
+    // from the templates since
+    let locale = Locale::parse(&subscriber.locale).unwrap_or_default();
+
+    if Utc::now() - subscriber.token_created_at > options.token_lifetime {
+        tracing::info!("Subscription token has expired");
+        return failure_response(ConfirmationFailure::Expired, locale, &templates, &options);
+    }
+
+    match set_subscriber_confirmed(&pool, subscriber.id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::info!("Subscriber is no longer pending confirmation");
+            return failure_response(ConfirmationFailure::Invalid, locale, &templates, &options);
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
     }
 
-    HttpResponse::Ok().finish()
+    if let Some(url) = &options.success_url {
+        return redirect(url);
+    }
+    let variables = HashMap::from([("name", subscriber.name)]);
+    render_page(&templates, "confirmed", locale, &variables, StatusCode::OK)
+}
+
+fn failure_response(
+    failure: ConfirmationFailure,
+    locale: Locale,
+    templates: &Templates,
+    options: &ConfirmationOptions,
+) -> HttpResponse {
+    match &options.failure_url {
+        Some(url) => {
+            let mut url = url.clone();
+            url.query_pairs_mut()
+                .append_pair("reason", failure.as_str());
+            redirect(&url)
+        }
+        None => render_page(
+            templates,
+            failure.page(),
+            locale,
+            &HashMap::new(),
+            failure.status(),
+        ),
+    }
+}
+
+fn redirect(url: &Url) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, url.as_str()))
+        .finish()
+}
+
+fn render_page(
+    templates: &Templates,
+    name: &str,
+    locale: Locale,
+    variables: &HashMap<&str, String>,
+    status: StatusCode,
+) -> HttpResponse {
+    match templates.render_page(name, locale, variables) {
+        Ok(page) => HttpResponse::build(status)
+            .content_type(ContentType::html())
+            .body(page),
+        Err(err) => {
+            tracing::error!(error = %err, page = name, "Failed to render page");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The subscriber a subscription token belongs to.
+struct TokenOwner {
+    id: Uuid,
+    name: String,
+    locale: String,
+    token_created_at: DateTime<Utc>,
 }
 
 /// Looks up the subscriber to whom the `subscription_token` belongs. There may not
 /// be a matching subscriber.
-#[tracing::instrument(
-    name = "Look up subscriber_id from token",
-    skip(pool, subscription_token)
-)]
-async fn get_subscriber_id_from_token(
+#[tracing::instrument(name = "Look up subscriber from token", skip(pool, subscription_token))]
+async fn get_subscriber_from_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens
-        WHERE subscription_token = $1"#,
+) -> Result<Option<TokenOwner>, sqlx::Error> {
+    sqlx::query_as!(
+        TokenOwner,
+        r#"SELECT s.id, s.name, s.locale, t.created_at AS token_created_at
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1"#,
         subscription_token
     )
     .fetch_optional(pool)
@@ -53,24 +212,39 @@ async fn get_subscriber_id_from_token(
         tracing::error!("Failed to execute query: {:?}", err);
         err
     })
-    .map(|row| row.map(|r| r.subscriber_id))
 }
 
-/// Marks the subscriber with ID `subscriber_id` as 'confirmed' in the database.
+/// Marks the subscriber with ID `subscriber_id` as 'confirmed' in the database, if
+/// they are still pending confirmation. Returns whether they were.
+///
+/// Their tokens are deleted either way, so confirmation links only work once: an
+/// old one can't take back an unsubscription.
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, subscriber_id))]
-async fn set_subscriber_confirmed(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+async fn set_subscriber_confirmed(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let log_error = |err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    };
+    let mut transaction = pool.begin().await.map_err(log_error)?;
+    let confirmed = sqlx::query!(
         r#"UPDATE subscriptions
         SET status = 'confirmed'
-        WHERE id = $1"#,
+        WHERE id = $1 AND status = 'pending_confirmation'"#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await
-    .map_err(|err| {
-        tracing::error!("Failed to execute query: {:?}", err);
-        err
-    })?;
+    .map_err(log_error)?
+    .rows_affected()
+        > 0;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(log_error)?;
+    transaction.commit().await.map_err(log_error)?;
 
-    Ok(())
+    Ok(confirmed)
 }
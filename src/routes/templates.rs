@@ -0,0 +1,68 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    domain::Locale,
+    templates::{Preview, TemplateError, Templates},
+};
+
+/// Which body of the email to preview.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewFormat {
+    #[default]
+    Html,
+    Text,
+}
+
+#[derive(Deserialize)]
+pub struct PreviewParameters {
+    #[serde(default)]
+    format: PreviewFormat,
+    /// A language tag like `fr`. English if not set.
+    locale: Option<String>,
+}
+
+/// Renders the email or page template called `name` with sample data.
+///
+/// For emails, responds with the HTML body, or the text body with `?format=text`.
+/// The subject line is in the `X-Email-Subject` header. Pass `?locale=` to see a
+/// translation.
+///
+/// Only served with the `template_previews` setting, which local development has on.
+#[tracing::instrument(name = "Previewing template", skip(parameters, templates))]
+#[get("/templates/{name}/preview")]
+pub async fn preview_template(
+    name: web::Path<String>,
+    parameters: web::Query<PreviewParameters>,
+    templates: web::Data<Templates>,
+) -> HttpResponse {
+    let locale = match parameters.locale.as_deref().map(Locale::parse) {
+        Some(Ok(locale)) => locale,
+        Some(Err(_)) => return HttpResponse::BadRequest().finish(),
+        None => Locale::default(),
+    };
+
+    let rendered = match templates.render_preview(&name, locale) {
+        Ok(Preview::Email(rendered)) => rendered,
+        Ok(Preview::Page(html)) => {
+            return HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body(html)
+        }
+        Err(TemplateError::NotFound(_)) => return HttpResponse::NotFound().finish(),
+        Err(err) => {
+            tracing::error!(error = %err, "Failed to render template preview");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let (content_type, body) = match parameters.format {
+        PreviewFormat::Html => (ContentType::html(), rendered.html_body),
+        PreviewFormat::Text => (ContentType::plaintext(), rendered.text_body),
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("X-Email-Subject", rendered.subject))
+        .body(body)
+}
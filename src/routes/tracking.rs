@@ -0,0 +1,111 @@
+use actix_web::{
+    get,
+    http::header::{self, CacheControl, CacheDirective},
+    web, HttpResponse,
+};
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    startup::HmacSecret,
+    tracking::{TrackingEvent, TrackingToken},
+};
+
+/// A transparent 1x1 GIF
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Records a click on a tracked link and sends the subscriber on to the original URL.
+///
+/// Only URLs inside a token we signed ourselves are followed, anything else is a 404.
+#[tracing::instrument(name = "Tracking a click", skip(token, pool, secret))]
+#[get("/t/c/{token}")]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let token = match TrackingToken::verify(&token, &secret.0) {
+        Ok(token) => token,
+        Err(err) => {
+            tracing::warn!(error.cause_chain = ?err, "Rejected click tracking token");
+            return HttpResponse::NotFound().finish();
+        }
+    };
+
+    let url = match &token.event {
+        TrackingEvent::Click { url } => url.clone(),
+        TrackingEvent::Open => return HttpResponse::NotFound().finish(),
+    };
+
+    // Losing an event is better than leaving the subscriber stranded
+    let _ = record_event(&pool, &token).await;
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish()
+}
+
+/// Records an open of a newsletter issue and serves the tracking pixel.
+#[tracing::instrument(name = "Tracking an open", skip(token, pool, secret))]
+#[get("/t/o/{token}.gif")]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let token = match TrackingToken::verify(&token, &secret.0) {
+        Ok(token) => token,
+        Err(err) => {
+            tracing::warn!(error.cause_chain = ?err, "Rejected open tracking token");
+            return HttpResponse::NotFound().finish();
+        }
+    };
+
+    if token.event != TrackingEvent::Open {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let _ = record_event(&pool, &token).await;
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        // Every load of the pixel should reach us
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::NoCache,
+        ]))
+        .body(PIXEL)
+}
+
+/// Stores the event described by `token` in the database.
+#[tracing::instrument(name = "Saving tracking event in database", skip(pool))]
+async fn record_event(pool: &PgPool, token: &TrackingToken) -> Result<(), sqlx::Error> {
+    let url = match &token.event {
+        TrackingEvent::Click { url } => Some(url.as_str()),
+        TrackingEvent::Open => None,
+    };
+
+    sqlx::query!(
+        r#"INSERT INTO tracking_events (id, subscriber_id, issue_id, kind, url, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6)"#,
+        Uuid::new_v4(),
+        token.subscriber_id,
+        token.issue_id,
+        token.event.kind(),
+        url,
+        Utc::now()
+    )
+    .execute(pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
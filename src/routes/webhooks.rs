@@ -0,0 +1,278 @@
+use actix_web::{http::header, post, web, HttpRequest, HttpResponse};
+use base64::Engine;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    domain::{SubscriberEmail, SuppressionReason},
+    email_log::mark_bounced,
+};
+
+/// How the Postmark webhook authenticates calls and handles bounces, see
+/// `PostmarkWebhookSettings`
+#[derive(Clone)]
+pub struct PostmarkWebhookOptions {
+    /// Postmark sends these as HTTP basic auth credentials on every webhook call
+    pub username: String,
+    pub password: Secret<String>,
+    /// Number of soft bounces after which we stop sending to an address
+    pub soft_bounce_threshold: i32,
+}
+
+/// The webhook payloads we care about. Postmark tells them apart with the
+/// `RecordType` field. See <https://postmarkapp.com/developer/webhooks/webhooks-overview>.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Bounce(Bounce),
+    SpamComplaint(SpamComplaint),
+    SubscriptionChange(SubscriptionChange),
+    /// Deliveries, opens, clicks and whatever else Postmark adds in the future
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Bounce {
+    /// Postmark's bounce type, e.g. `HardBounce` or `SoftBounce`
+    r#type: String,
+    email: String,
+    /// The ID Postmark gave the email that bounced when we sent it
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct SpamComplaint {
+    email: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct SubscriptionChange {
+    recipient: String,
+    /// `true` when the address was added to Postmark's suppression list, `false`
+    /// when it was taken off again.
+    suppress_sending: bool,
+    suppression_reason: Option<String>,
+}
+
+/// How a bounce affects the subscriber it happened to.
+enum BounceSeverity {
+    /// The address will never work, stop sending right away.
+    Hard,
+    /// Might go away on its own, but too many of these and we give up.
+    Soft,
+    /// Auto-responders and the like, nothing to do.
+    Ignored,
+}
+
+impl Bounce {
+    fn severity(&self) -> BounceSeverity {
+        match self.r#type.as_str() {
+            "HardBounce" | "BadEmailAddress" | "ManuallyDeactivated" => BounceSeverity::Hard,
+            "SoftBounce" | "Transient" | "DnsError" | "Blocked" => BounceSeverity::Soft,
+            _ => BounceSeverity::Ignored,
+        }
+    }
+}
+
+/// Receives bounce, spam complaint and suppression notifications from Postmark, and
+/// stops us sending to the affected subscribers.
+///
+/// Requests must carry the basic auth credentials from `PostmarkWebhookOptions`.
+#[tracing::instrument(
+    name = "Handling a Postmark webhook",
+    skip(request, body, pool, settings)
+)]
+#[post("/webhooks/postmark")]
+pub async fn postmark_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    settings: web::Data<PostmarkWebhookOptions>,
+) -> HttpResponse {
+    if !is_authorized(&request, &settings) {
+        return HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, r#"Basic realm="postmark""#))
+            .finish();
+    }
+
+    let event: PostmarkEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(err) => {
+            tracing::warn!(error.cause_chain = ?err, "Failed to parse Postmark webhook");
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+
+    let outcome = match event {
+        PostmarkEvent::Bounce(bounce) => {
+            if let Some(message_id) = &bounce.message_id {
+                // The log is only informational, suppressing the subscriber is what matters
+                let _ = mark_bounced(pool.get_ref(), message_id, &bounce.r#type).await;
+            }
+            let Some(email) = parse_recipient(&bounce.email) else {
+                return HttpResponse::Ok().finish();
+            };
+            match bounce.severity() {
+                BounceSeverity::Hard => {
+                    suppress_subscriber(&pool, &email, SuppressionReason::HardBounce).await
+                }
+                BounceSeverity::Soft => {
+                    record_soft_bounce(&pool, &email, settings.soft_bounce_threshold).await
+                }
+                BounceSeverity::Ignored => Ok(()),
+            }
+        }
+        PostmarkEvent::SpamComplaint(complaint) => {
+            let Some(email) = parse_recipient(&complaint.email) else {
+                return HttpResponse::Ok().finish();
+            };
+            suppress_subscriber(&pool, &email, SuppressionReason::SpamComplaint).await
+        }
+        PostmarkEvent::SubscriptionChange(change) => {
+            let Some(email) = parse_recipient(&change.recipient) else {
+                return HttpResponse::Ok().finish();
+            };
+            if change.suppress_sending {
+                let reason = match change.suppression_reason.as_deref() {
+                    Some("HardBounce") => SuppressionReason::HardBounce,
+                    Some("SpamComplaint") => SuppressionReason::SpamComplaint,
+                    _ => SuppressionReason::Manual,
+                };
+                suppress_subscriber(&pool, &email, reason).await
+            } else {
+                reactivate_subscriber(&pool, &email).await
+            }
+        }
+        PostmarkEvent::Other => Ok(()),
+    };
+
+    match outcome {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Checks the basic auth credentials on `request` against the ones we expect Postmark
+/// to send. Compared in constant time, so response times don't give away how much
+/// of a guess was right.
+fn is_authorized(request: &HttpRequest, settings: &PostmarkWebhookOptions) -> bool {
+    let credentials = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| {
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .ok()
+        })
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+
+    match credentials.as_deref().and_then(|c| c.split_once(':')) {
+        Some((username, password)) => {
+            let username_matches = username.as_bytes().ct_eq(settings.username.as_bytes());
+            let password_matches = password
+                .as_bytes()
+                .ct_eq(settings.password.expose_secret().as_bytes());
+            (username_matches & password_matches).into()
+        }
+        None => false,
+    }
+}
+
+/// The address a Postmark event is about. Postmark may spell it differently than the
+/// subscriber did, so subscribers are looked up by its canonical form. Events about
+/// addresses we could never have sent to are ignored.
+fn parse_recipient(email: &str) -> Option<SubscriberEmail> {
+    match SubscriberEmail::parse(email.to_string()) {
+        Ok(email) => Some(email),
+        Err(err) => {
+            tracing::warn!(error = %err, "Ignored Postmark event about an invalid address");
+            None
+        }
+    }
+}
+
+/// Stops all future sends to `email`. Subscribers that are already suppressed keep
+/// their original reason.
+#[tracing::instrument(name = "Suppress subscriber", skip(pool))]
+async fn suppress_subscriber(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    reason: SuppressionReason,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions
+        SET suppressed_at = $2, suppression_reason = $3
+        WHERE canonical_email = $1 AND suppressed_at IS NULL"#,
+        email.canonical(),
+        Utc::now(),
+        reason.as_str()
+    )
+    .execute(pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+/// Counts a soft bounce against `email`, suppressing it once it has bounced
+/// `threshold` times.
+#[tracing::instrument(name = "Record soft bounce", skip(pool))]
+async fn record_soft_bounce(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    threshold: i32,
+) -> Result<(), sqlx::Error> {
+    let soft_bounce_count = sqlx::query!(
+        r#"UPDATE subscriptions
+        SET soft_bounce_count = soft_bounce_count + 1
+        WHERE canonical_email = $1
+        RETURNING soft_bounce_count"#,
+        email.canonical()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?
+    .map(|row| row.soft_bounce_count);
+
+    match soft_bounce_count {
+        Some(count) if count >= threshold => {
+            suppress_subscriber(pool, email, SuppressionReason::RepeatedSoftBounces).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Lifts a suppression on `email`, giving it a clean slate.
+#[tracing::instrument(name = "Reactivate subscriber", skip(pool))]
+async fn reactivate_subscriber(pool: &PgPool, email: &SubscriberEmail) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions
+        SET suppressed_at = NULL, suppression_reason = NULL, soft_bounce_count = 0
+        WHERE canonical_email = $1"#,
+        email.canonical()
+    )
+    .execute(pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::Sha256;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SigningError {
+    #[error("The signed value is malformed")]
+    Malformed,
+    #[error("The signature is invalid")]
+    InvalidSignature,
+}
+
+/// What a value is signed for. Part of the signature, so a value signed for one
+/// purpose can't be passed off as another, e.g. a flash message as a CSRF cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    Csrf,
+    Flash,
+    FormTimestamp,
+    Tracking,
+}
+
+impl Purpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Csrf => "csrf",
+            Self::Flash => "flash",
+            Self::FormTimestamp => "form_timestamp",
+            Self::Tracking => "tracking",
+        }
+    }
+}
+
+/// Serializes `value` and signs it with `secret` for `purpose`, so it can be handed
+/// out and trusted when it comes back. The result is URL and cookie safe.
+pub fn sign<T: Serialize>(purpose: Purpose, value: &T, secret: &Secret<String>) -> String {
+    let payload = serde_json::to_vec(value).expect("Failed to serialize signed value");
+    let signature = mac(purpose, secret, &payload).finalize().into_bytes();
+
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(signature)
+    )
+}
+
+/// Parses a value produced by `sign`, checking that it was signed with `secret`
+/// for `purpose` and hasn't been tampered with since.
+pub fn verify<T: DeserializeOwned>(
+    purpose: Purpose,
+    signed: &str,
+    secret: &Secret<String>,
+) -> Result<T, SigningError> {
+    let (payload, signature) = signed.split_once('.').ok_or(SigningError::Malformed)?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| SigningError::Malformed)?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| SigningError::Malformed)?;
+
+    mac(purpose, secret, &payload)
+        .verify_slice(&signature)
+        .map_err(|_| SigningError::InvalidSignature)?;
+
+    serde_json::from_slice(&payload).map_err(|_| SigningError::Malformed)
+}
+
+fn mac(purpose: Purpose, secret: &Secret<String>, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any size");
+    // Purposes never contain the separator, so no purpose and payload can sign the
+    // same bytes as another
+    mac.update(purpose.as_str().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{sign, verify, Purpose};
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn values_verify_for_the_purpose_they_were_signed_for() {
+        let signed = sign(Purpose::Flash, &"Welcome!", &secret());
+
+        assert_ok!(verify::<String>(Purpose::Flash, &signed, &secret()));
+    }
+
+    #[test]
+    fn values_signed_for_another_purpose_are_rejected() {
+        let signed = sign(Purpose::Flash, &"Welcome!", &secret());
+
+        for purpose in [Purpose::Csrf, Purpose::FormTimestamp, Purpose::Tracking] {
+            assert_err!(verify::<String>(purpose, &signed, &secret()));
+        }
+    }
+}
@@ -1,20 +1,28 @@
-use std::net::TcpListener;
+use std::{net::TcpListener, sync::Arc};
 
 use actix_web::{dev::Server, web, App, HttpServer};
+use secrecy::Secret;
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
-use url::Url;
 
 use crate::{
-    configuration::{DatabaseSettings, Settings},
+    configuration::{ApplicationSettings, DatabaseSettings, Settings},
+    cors::any_origin_middleware,
     email_client::EmailClient,
-    routes::{confirm, health_check, subscribe},
+    request_limiter::RequestLimiter,
+    routes::{
+        confirm, create_embedded_subscription, create_subscription, health_check, postmark_webhook,
+        preview_template, subscribe, subscribe_form, track_click, track_open, versioned_widget,
+        widget, widget_snippet, PostmarkWebhookOptions,
+    },
+    templates::Templates,
 };
 
 /// A running application
 pub struct Application {
     port: u16,
     server: Server,
+    email_client: Arc<EmailClient>,
 }
 
 impl Application {
@@ -23,24 +31,38 @@ impl Application {
     pub async fn build(settings: Settings) -> std::io::Result<Self> {
         let connection_pool = get_connection_pool(&settings.database);
 
-        let email_config = settings.email_client;
-        let base_url = Url::parse(&email_config.base_url).expect("Invalid base URL");
-        let sender_email = email_config.sender().expect("Invalid sender email address");
-        let timeout = email_config.timeout();
-        let email_client = EmailClient::new(
-            base_url,
-            sender_email,
-            email_config.authorization_token,
-            timeout,
-        );
+        let email_client = settings
+            .email_client
+            .client()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        let email_client = Arc::new(email_client);
+        let request_limiter = Arc::new(settings.request_limits.limiter(connection_pool.clone()));
+        let postmark_webhook_options = settings
+            .postmark_webhook
+            .options()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
 
         let app_config = settings.application;
+        let templates = Templates::load(&app_config.templates_directory)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
         let app_address = format!("{}:{}", &app_config.host, app_config.port);
         let listener = TcpListener::bind(app_address)?;
         let port = listener.local_addr().unwrap().port();
 
-        let server = run(listener, connection_pool, email_client, app_config.base_url)?;
-        Ok(Self { port, server })
+        let server = run(
+            listener,
+            connection_pool,
+            email_client.clone(),
+            request_limiter,
+            templates,
+            app_config,
+            postmark_webhook_options,
+        )?;
+        Ok(Self {
+            port,
+            server,
+            email_client,
+        })
     }
 
     /// The port that the app is listening on
@@ -48,6 +70,12 @@ impl Application {
         self.port
     }
 
+    /// The email client of the app. Background workers should send through this one
+    /// too, so there is a single circuit breaker and rate limiter per provider.
+    pub fn email_client(&self) -> Arc<EmailClient> {
+        self.email_client.clone()
+    }
+
     /// Listen and handle requests until we receive a stop signal
     pub async fn run_until_stopped(self) -> std::io::Result<()> {
         self.server.await
@@ -62,26 +90,94 @@ pub fn get_connection_pool(settings: &DatabaseSettings) -> PgPool {
 /// Wrapper for base URL for building API requests. Need a wrapper so we can register with app data.
 pub struct ApplicationBaseUrl(pub String);
 
+/// Wrapper for the key we sign tokens with. Need a wrapper so we can register with app data.
+pub struct HmacSecret(pub Secret<String>);
+
+/// Whether subscription form posts without a CSRF token are accepted. Need a wrapper so
+/// we can register with app data.
+pub struct LegacyFormPosts(pub bool);
+
 /// Starts a server, listening on `listener`, running in the background and returns it
 fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
+    email_client: Arc<EmailClient>,
+    request_limiter: Arc<RequestLimiter>,
+    templates: Templates,
+    app_config: ApplicationSettings,
+    postmark_webhook_options: PostmarkWebhookOptions,
 ) -> std::io::Result<Server> {
     let db_pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let email_client = web::Data::from(email_client);
+    let request_limiter = web::Data::from(request_limiter);
+    let templates = web::Data::new(templates);
+    let confirmation_options = app_config
+        .confirmation_options()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let confirmation_options = web::Data::new(confirmation_options);
+    let bot_check = web::Data::new(app_config.bot_check());
+    let email_policy = web::Data::new(app_config.email_policy.policy()?);
+    let email_suggester = web::Data::new(app_config.email_suggestions.suggester());
+    let cors_policy = app_config
+        .cors
+        .policy()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let hmac_secret = app_config
+        .hmac_secret()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let base_url = web::Data::new(ApplicationBaseUrl(app_config.base_url));
+    let hmac_secret = web::Data::new(HmacSecret(hmac_secret));
+    let legacy_form_posts = web::Data::new(LegacyFormPosts(app_config.legacy_form_posts));
+    let template_previews = app_config.template_previews;
+    let postmark_webhook_options = web::Data::new(postmark_webhook_options);
 
     let server = HttpServer::new(move || {
         App::new()
             .wrap(TracingLogger::default())
             .service(health_check)
-            .service(subscribe)
+            // The public subscription endpoints, partner sites may call them from
+            // their pages
+            .service(
+                web::resource("/subscribe")
+                    .wrap(cors_policy.middleware())
+                    .route(web::get().to(subscribe_form))
+                    .route(web::post().to(subscribe)),
+            )
+            .service(
+                web::resource("/subscriptions")
+                    .wrap(cors_policy.middleware())
+                    .route(web::post().to(create_subscription)),
+            )
+            // The widget posts here from any page
+            .service(
+                web::resource("/embed/subscriptions")
+                    .wrap(any_origin_middleware())
+                    .route(web::post().to(create_embedded_subscription)),
+            )
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .service(postmark_webhook)
+            .service(track_click)
+            .service(track_open)
+            .configure(|cfg| {
+                if template_previews {
+                    cfg.service(preview_template);
+                }
+            })
+            .service(widget)
+            .service(versioned_widget)
+            .service(widget_snippet)
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(request_limiter.clone())
+            .app_data(templates.clone())
+            .app_data(confirmation_options.clone())
             .app_data(base_url.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(legacy_form_posts.clone())
+            .app_data(bot_check.clone())
+            .app_data(email_policy.clone())
+            .app_data(email_suggester.clone())
+            .app_data(postmark_webhook_options.clone())
     })
     .listen(listener)?
     .run();
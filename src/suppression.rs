@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+
+use sqlx::{Executor, Postgres};
+
+use crate::{domain::SubscriberEmail, email_message::EmailMessage};
+
+/// Messages to addresses we haven't stopped sending to. The batch API only takes
+/// these, so it can't be handed a suppressed recipient by mistake.
+#[derive(Debug, Clone)]
+pub struct UnsuppressedMessages(Vec<EmailMessage>);
+
+impl UnsuppressedMessages {
+    /// Leaves out the messages in `messages` to suppressed subscribers, keeping the
+    /// others in order. Recipients are matched on their canonical address, since
+    /// messages may spell it differently than the subscriber did.
+    pub async fn filter(
+        executor: impl Executor<'_, Database = Postgres>,
+        messages: Vec<EmailMessage>,
+    ) -> Result<Self, sqlx::Error> {
+        let recipients: Vec<String> = messages
+            .iter()
+            .map(|message| message.recipient().canonical())
+            .collect();
+        let suppressed: HashSet<String> = sqlx::query!(
+            r#"SELECT canonical_email FROM subscriptions
+            WHERE canonical_email = ANY($1) AND suppressed_at IS NOT NULL"#,
+            &recipients[..]
+        )
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(|subscriber| subscriber.canonical_email)
+        .collect();
+
+        let count = messages.len();
+        let messages: Vec<EmailMessage> = messages
+            .into_iter()
+            .zip(recipients)
+            .filter(|(_, recipient)| !suppressed.contains(recipient))
+            .map(|(message, _)| message)
+            .collect();
+        if messages.len() < count {
+            tracing::info!(
+                dropped = count - messages.len(),
+                "Dropping messages to suppressed addresses"
+            );
+        }
+        Ok(Self(messages))
+    }
+
+    /// Takes `messages` as they are, for tests that have no database to check them
+    /// against.
+    #[cfg(test)]
+    pub(crate) fn unchecked(messages: Vec<EmailMessage>) -> Self {
+        Self(messages)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn into_inner(self) -> Vec<EmailMessage> {
+        self.0
+    }
+}
+
+/// Whether we stopped sending to the mailbox of `email`. Matched on the canonical
+/// address, since `email` may spell it differently than the subscriber did.
+pub async fn is_suppressed(
+    executor: impl Executor<'_, Database = Postgres>,
+    email: &SubscriberEmail,
+) -> Result<bool, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        "SELECT suppressed_at FROM subscriptions WHERE canonical_email = $1",
+        email.canonical()
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(subscriber.is_some_and(|subscriber| subscriber.suppressed_at.is_some()))
+}
@@ -0,0 +1,881 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    domain::{Locale, SubscriberEmail, SubscriberName},
+    issue_content::Stylesheet,
+};
+
+/// An email or page we render from a template, and the variables its template
+/// may use.
+struct TemplateSpec {
+    name: &'static str,
+    variables: &'static [&'static str],
+    /// Values for every variable, for previews
+    sample: &'static [(&'static str, &'static str)],
+}
+
+/// The CSS inlined into issues written in Markdown, in the templates directory
+const ISSUE_STYLESHEET: &str = "issue.css";
+
+/// Every templated email. Each needs `<name>.subject.txt`, `<name>.html` and
+/// `<name>.txt` in the directory of each locale it is translated to.
+const EMAILS: &[TemplateSpec] = &[TemplateSpec {
+    name: "confirmation",
+    variables: &["name", "confirmation_link"],
+    sample: &[
+        ("name", "Ursula Le Guin"),
+        (
+            "confirmation_link",
+            "https://example.com/subscriptions/confirm?subscription_token=sample",
+        ),
+    ],
+}];
+
+/// Every templated web page. Each needs `<name>.html` in the directory of each
+/// locale it is translated to.
+const PAGES: &[TemplateSpec] = &[
+    TemplateSpec {
+        name: "confirmed",
+        variables: &["name"],
+        sample: &[("name", "Ursula Le Guin")],
+    },
+    TemplateSpec {
+        name: "confirmation_expired",
+        variables: &[],
+        sample: &[],
+    },
+    TemplateSpec {
+        name: "confirmation_invalid",
+        variables: &[],
+        sample: &[],
+    },
+    TemplateSpec {
+        name: "subscribe",
+        variables: &["csrf_token", "rendered_at", "error", "name", "email"],
+        sample: &[
+            ("csrf_token", "sample"),
+            ("rendered_at", "sample"),
+            ("error", "ursula_le_guin is not a valid subscriber email."),
+            ("name", "Ursula Le Guin"),
+            ("email", "ursula_le_guin"),
+        ],
+    },
+    TemplateSpec {
+        name: "email_suggestion",
+        variables: &["csrf_token", "rendered_at", "suggestion", "name", "email"],
+        sample: &[
+            ("csrf_token", "sample"),
+            ("rendered_at", "sample"),
+            ("suggestion", "ursula_le_guin@gmail.com"),
+            ("name", "Ursula Le Guin"),
+            ("email", "ursula_le_guin@gmial.com"),
+        ],
+    },
+    TemplateSpec {
+        name: "subscribed",
+        variables: &["email"],
+        sample: &[("email", "ursula_le_guin@gmail.com")],
+    },
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Failed to read template {path}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Invalid template {template}: {message}")]
+    Syntax { template: String, message: String },
+    #[error("Template {template} uses the unknown variable {variable}")]
+    UnknownVariable { template: String, variable: String },
+    #[error("There is no template called {0}")]
+    NotFound(String),
+    #[error("No value was given for the template variable {0}")]
+    MissingVariable(String),
+}
+
+/// How variable values are escaped when they are put into a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    Html,
+    None,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(Placeholder),
+}
+
+/// A `{{ variable }}` or `{{ variable | default: "value" }}` in a template.
+#[derive(Debug, PartialEq)]
+struct Placeholder {
+    name: String,
+    /// Used when the variable has no value, or an empty one
+    default: Option<String>,
+}
+
+impl Placeholder {
+    fn parse(source: &str) -> Result<Self, String> {
+        let (name, filter) = match source.split_once('|') {
+            Some((name, filter)) => (name.trim(), Some(filter.trim())),
+            None => (source.trim(), None),
+        };
+        if !is_variable_name(name) {
+            return Err(format!("`{}` is not a valid variable name", name));
+        }
+        let default = filter.map(parse_default_filter).transpose()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            default,
+        })
+    }
+}
+
+/// Reads the value out of a `default: "value"` filter. Single quotes work too.
+fn parse_default_filter(filter: &str) -> Result<String, String> {
+    let argument = filter
+        .strip_prefix("default")
+        .and_then(|rest| rest.trim_start().strip_prefix(':'))
+        .ok_or_else(|| format!("`{}` is not a known filter, only `default` is", filter))?
+        .trim();
+    ['"', '\'']
+        .iter()
+        .find_map(|quote| {
+            argument
+                .strip_prefix(*quote)
+                .and_then(|rest| rest.strip_suffix(*quote))
+                .filter(|value| !value.contains(*quote))
+        })
+        .map(str::to_string)
+        .ok_or_else(|| format!("The default `{}` must be a quoted string", argument))
+}
+
+/// A parsed template: text, with `{{variable}}` placeholders.
+#[derive(Debug)]
+struct Template {
+    segments: Vec<Segment>,
+    escape: Escape,
+}
+
+impl Template {
+    fn parse(source: &str, escape: Escape) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or_else(|| "A `{{` is never closed".to_string())?;
+            segments.push(Segment::Variable(Placeholder::parse(&after_open[..end])?));
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        Ok(Self { segments, escape })
+    }
+
+    fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Variable(placeholder) => Some(placeholder),
+            Segment::Text(_) => None,
+        })
+    }
+
+    fn render<K>(&self, variables: &HashMap<K, String>) -> Result<String, TemplateError>
+    where
+        K: Borrow<str> + Hash + Eq,
+    {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable(placeholder) => {
+                    let value = variables
+                        .get(placeholder.name.as_str())
+                        .filter(|value| !value.is_empty())
+                        .or(placeholder.default.as_ref())
+                        .ok_or_else(|| TemplateError::MissingVariable(placeholder.name.clone()))?;
+                    match self.escape {
+                        Escape::Html => rendered.push_str(&escape_html(value)),
+                        Escape::None => rendered.push_str(value),
+                    }
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+/// Lowercase identifiers, optionally namespaced with dots like `attributes.plan`.
+fn is_variable_name(name: &str) -> bool {
+    name.split('.').all(|part| {
+        let mut chars = part.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_')
+            && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    })
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The subject line and bodies of a templated email.
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+struct EmailTemplate {
+    subject: Template,
+    html: Template,
+    text: Template,
+}
+
+/// A template rendered with sample data.
+#[derive(Debug)]
+pub enum Preview {
+    Email(RenderedEmail),
+    /// The HTML of a web page
+    Page(String),
+}
+
+/// The templates of every email we send and every page we show, loaded from a
+/// directory so the copy can change without a deploy.
+///
+/// Translations live in a directory per locale, like `fr/`. English is required,
+/// other locales fall back to it for anything they don't translate.
+///
+/// Values put into HTML are HTML-escaped. Email subjects and text bodies get them
+/// as they are.
+pub struct Templates {
+    emails: Translations<EmailTemplate>,
+    pages: Translations<Template>,
+    issue_stylesheet: Stylesheet,
+}
+
+impl Templates {
+    /// Loads and checks every template from `directory`, and the stylesheet for
+    /// issues.
+    ///
+    /// Fails if an English template is missing, if a translation is incomplete,
+    /// or if a template is malformed or uses a variable its email or page doesn't
+    /// provide. This way mistakes show up at startup rather than when sending.
+    pub fn load(directory: impl AsRef<Path>) -> Result<Self, TemplateError> {
+        let directory = directory.as_ref();
+        let mut emails = HashMap::new();
+        let mut pages = HashMap::new();
+        for locale in Locale::ALL {
+            let locale_directory = directory.join(locale.as_str());
+            for spec in EMAILS {
+                let path =
+                    |extension: &str| locale_directory.join(format!("{}.{}", spec.name, extension));
+                let paths = [path("subject.txt"), path("html"), path("txt")];
+                if locale != Locale::English && !paths.iter().any(|path| path.exists()) {
+                    continue;
+                }
+                let [subject, html, text] = paths;
+                let template = EmailTemplate {
+                    subject: load_template(&subject, Escape::None, spec.variables)?,
+                    html: load_template(&html, Escape::Html, spec.variables)?,
+                    text: load_template(&text, Escape::None, spec.variables)?,
+                };
+                emails
+                    .entry(locale)
+                    .or_insert_with(HashMap::new)
+                    .insert(spec.name, template);
+            }
+            for spec in PAGES {
+                let path = locale_directory.join(format!("{}.html", spec.name));
+                if locale != Locale::English && !path.exists() {
+                    continue;
+                }
+                pages.entry(locale).or_insert_with(HashMap::new).insert(
+                    spec.name,
+                    load_template(&path, Escape::Html, spec.variables)?,
+                );
+            }
+        }
+
+        let path = directory.join(ISSUE_STYLESHEET);
+        let css = std::fs::read_to_string(&path).map_err(|source| TemplateError::Read {
+            path: path.clone(),
+            source,
+        })?;
+        let issue_stylesheet =
+            Stylesheet::parse(&css).map_err(|message| TemplateError::Syntax {
+                template: path.display().to_string(),
+                message,
+            })?;
+
+        Ok(Self {
+            emails,
+            pages,
+            issue_stylesheet,
+        })
+    }
+
+    /// The CSS to inline into issues written in Markdown.
+    pub fn issue_stylesheet(&self) -> &Stylesheet {
+        &self.issue_stylesheet
+    }
+
+    /// Renders the email called `name` in `locale`, with the given variable values.
+    pub fn render(
+        &self,
+        name: &str,
+        locale: Locale,
+        variables: &HashMap<&str, String>,
+    ) -> Result<RenderedEmail, TemplateError> {
+        let template = translated(&self.emails, name, locale)?;
+
+        Ok(RenderedEmail {
+            subject: template.subject.render(variables)?,
+            html_body: template.html.render(variables)?,
+            text_body: template.text.render(variables)?,
+        })
+    }
+
+    /// Renders the page called `name` in `locale`, with the given variable values.
+    pub fn render_page(
+        &self,
+        name: &str,
+        locale: Locale,
+        variables: &HashMap<&str, String>,
+    ) -> Result<String, TemplateError> {
+        translated(&self.pages, name, locale)?.render(variables)
+    }
+
+    /// Renders the email or page called `name` in `locale` with made up values, to
+    /// see what it looks like.
+    pub fn render_preview(&self, name: &str, locale: Locale) -> Result<Preview, TemplateError> {
+        let sample = |spec: &TemplateSpec| -> HashMap<&str, String> {
+            spec.sample
+                .iter()
+                .map(|(variable, value)| (*variable, value.to_string()))
+                .collect()
+        };
+
+        if let Some(spec) = EMAILS.iter().find(|spec| spec.name == name) {
+            self.render(name, locale, &sample(spec)).map(Preview::Email)
+        } else if let Some(spec) = PAGES.iter().find(|spec| spec.name == name) {
+            self.render_page(name, locale, &sample(spec))
+                .map(Preview::Page)
+        } else {
+            Err(TemplateError::NotFound(name.to_string()))
+        }
+    }
+}
+
+/// Templates by locale, then by name.
+type Translations<T> = HashMap<Locale, HashMap<&'static str, T>>;
+
+/// The template called `name` in `locale`, or in English if it isn't translated.
+fn translated<'a, T>(
+    templates: &'a Translations<T>,
+    name: &str,
+    locale: Locale,
+) -> Result<&'a T, TemplateError> {
+    let in_locale = |locale| templates.get(&locale).and_then(|names| names.get(name));
+    in_locale(locale)
+        .or_else(|| in_locale(Locale::English))
+        .ok_or_else(|| TemplateError::NotFound(name.to_string()))
+}
+
+fn load_template(
+    path: &Path,
+    escape: Escape,
+    known_variables: &[&str],
+) -> Result<Template, TemplateError> {
+    let source = std::fs::read_to_string(path).map_err(|source| TemplateError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    parse_checked(
+        &source,
+        escape,
+        &path.display().to_string(),
+        |placeholder| {
+            if known_variables.contains(&placeholder.name.as_str()) {
+                PlaceholderCheck::Known
+            } else {
+                PlaceholderCheck::Unknown
+            }
+        },
+    )
+}
+
+enum PlaceholderCheck {
+    Known,
+    Unknown,
+    Invalid(String),
+}
+
+/// Parses `source`, then checks every placeholder in it with `check`. `template`
+/// names the template in errors.
+fn parse_checked(
+    source: &str,
+    escape: Escape,
+    template: &str,
+    check: impl Fn(&Placeholder) -> PlaceholderCheck,
+) -> Result<Template, TemplateError> {
+    let parsed = Template::parse(source, escape).map_err(|message| TemplateError::Syntax {
+        template: template.to_string(),
+        message,
+    })?;
+    for placeholder in parsed.placeholders() {
+        match check(placeholder) {
+            PlaceholderCheck::Known => {}
+            PlaceholderCheck::Unknown => {
+                return Err(TemplateError::UnknownVariable {
+                    template: template.to_string(),
+                    variable: placeholder.name.clone(),
+                })
+            }
+            PlaceholderCheck::Invalid(message) => {
+                return Err(TemplateError::Syntax {
+                    template: template.to_string(),
+                    message,
+                })
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Variables every newsletter issue may use. Custom attributes come on top, as
+/// `attributes.<key>`.
+const ISSUE_VARIABLES: &[&str] = &["name", "email", "subscribed_at", "unsubscribe_link"];
+
+/// What we know about the recipient of a newsletter issue, to personalize it with.
+pub struct MergeContext {
+    pub name: SubscriberName,
+    pub email: SubscriberEmail,
+    pub subscribed_at: DateTime<Utc>,
+    pub unsubscribe_link: String,
+    /// Custom attributes of the subscriber, by key
+    pub attributes: HashMap<String, String>,
+}
+
+impl MergeContext {
+    fn variables(&self) -> HashMap<String, String> {
+        let mut variables = HashMap::from([
+            ("name".to_string(), self.name.as_ref().to_string()),
+            ("email".to_string(), self.email.as_ref().to_string()),
+            (
+                "subscribed_at".to_string(),
+                self.subscribed_at.format("%B %-d, %Y").to_string(),
+            ),
+            (
+                "unsubscribe_link".to_string(),
+                self.unsubscribe_link.clone(),
+            ),
+        ]);
+        for (key, value) in &self.attributes {
+            variables.insert(format!("attributes.{}", key), value.clone());
+        }
+        variables
+    }
+}
+
+/// The content of a newsletter issue, personalized for every recipient with merge
+/// fields like `{{ name | default: "friend" }}`.
+///
+/// Issues can use the variables of a `MergeContext`. Not every subscriber has
+/// every custom attribute, so `attributes.<key>` always needs a default.
+///
+/// Nothing stores or sends issues yet. Whatever does should `parse` an issue when it
+/// is saved, and `render` it for every recipient when it is delivered.
+pub struct IssueTemplate {
+    title: Template,
+    html: Template,
+    text: Template,
+}
+
+impl IssueTemplate {
+    /// Parses and checks the content of an issue. Meant for when the issue is saved,
+    /// so authors find out about typos in variable names before anything is sent.
+    pub fn parse(
+        title: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<Self, TemplateError> {
+        let parse = |source: &str, escape: Escape, template: &str| {
+            parse_checked(source, escape, template, |placeholder| {
+                if ISSUE_VARIABLES.contains(&placeholder.name.as_str()) {
+                    PlaceholderCheck::Known
+                } else if placeholder.name.starts_with("attributes.") {
+                    match placeholder.default {
+                        Some(_) => PlaceholderCheck::Known,
+                        None => PlaceholderCheck::Invalid(format!(
+                            "`{}` needs a default, not every subscriber has it",
+                            placeholder.name
+                        )),
+                    }
+                } else {
+                    PlaceholderCheck::Unknown
+                }
+            })
+        };
+
+        Ok(Self {
+            title: parse(title, Escape::None, "issue title")?,
+            html: parse(html_content, Escape::Html, "issue HTML content")?,
+            text: parse(text_content, Escape::None, "issue text content")?,
+        })
+    }
+
+    /// Renders the issue for one recipient, when it is delivered.
+    pub fn render(&self, recipient: &MergeContext) -> Result<RenderedEmail, TemplateError> {
+        let variables = recipient.variables();
+
+        Ok(RenderedEmail {
+            subject: self.title.render(&variables)?,
+            html_body: self.html.render(&variables)?,
+            text_body: self.text.render(&variables)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok};
+    use tempfile::TempDir;
+
+    use super::{
+        Escape, IssueTemplate, MergeContext, Placeholder, Preview, Segment, Template,
+        TemplateError, Templates,
+    };
+    use crate::domain::{Locale, SubscriberEmail, SubscriberName};
+
+    fn variables(pairs: &[(&'static str, &str)]) -> HashMap<&'static str, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect()
+    }
+
+    fn variable(name: &str) -> Segment {
+        Segment::Variable(Placeholder {
+            name: name.into(),
+            default: None,
+        })
+    }
+
+    fn recipient(attributes: &[(&str, &str)]) -> MergeContext {
+        MergeContext {
+            name: SubscriberName::parse("Ursula".into()).unwrap(),
+            email: SubscriberEmail::parse("ursula_le_guin@gmail.com".into()).unwrap(),
+            subscribed_at: Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap(),
+            unsubscribe_link: "https://example.com/unsubscribe?token=abc".into(),
+            attributes: attributes
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// A copy of the real templates directory, to break in tests. It is deleted
+    /// when dropped.
+    fn templates_copy() -> TempDir {
+        let directory = TempDir::new().unwrap();
+        copy_directory("templates".as_ref(), directory.path());
+        directory
+    }
+
+    fn copy_directory(from: &std::path::Path, to: &std::path::Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            let target = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy_directory(&path, &target);
+            } else {
+                std::fs::copy(&path, target).unwrap();
+            }
+        }
+    }
+
+    fn confirmation_variables() -> HashMap<&'static str, String> {
+        variables(&[
+            ("name", "Ursula"),
+            ("confirmation_link", "https://example.com/confirm"),
+        ])
+    }
+
+    #[test]
+    fn variables_are_parsed_with_or_without_spaces() {
+        let template = Template::parse("Hi {{name}}, {{ name }}!", Escape::None).unwrap();
+
+        assert_eq!(
+            template.segments,
+            vec![
+                Segment::Text("Hi ".into()),
+                variable("name"),
+                Segment::Text(", ".into()),
+                variable("name"),
+                Segment::Text("!".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_variables_are_rejected() {
+        assert_err!(Template::parse("Hi {{name", Escape::None));
+    }
+
+    #[test]
+    fn invalid_variable_names_are_rejected() {
+        for source in ["{{}}", "{{ first name }}", "{{Name}}", "{{1st}}"] {
+            assert_err!(Template::parse(source, Escape::None));
+        }
+    }
+
+    #[test]
+    fn html_templates_escape_values() {
+        let template = Template::parse("<p>{{name}}</p>", Escape::Html).unwrap();
+
+        let rendered = template
+            .render(&variables(&[("name", "<b>Tom & Jerry's</b>")]))
+            .unwrap();
+
+        assert_eq!(rendered, "<p>&lt;b&gt;Tom &amp; Jerry&#39;s&lt;/b&gt;</p>");
+    }
+
+    #[test]
+    fn text_templates_keep_values_as_they_are() {
+        let template = Template::parse("Hi {{name}}", Escape::None).unwrap();
+
+        let rendered = template.render(&variables(&[("name", "Tom & Jerry")]));
+
+        assert_eq!(rendered.unwrap(), "Hi Tom & Jerry");
+    }
+
+    #[test]
+    fn rendering_without_a_value_for_a_variable_fails() {
+        let template = Template::parse("Hi {{name}}", Escape::None).unwrap();
+
+        let result = template.render(&variables(&[]));
+
+        assert!(matches!(result, Err(TemplateError::MissingVariable(name)) if name == "name"));
+    }
+
+    #[test]
+    fn the_shipped_templates_load_and_preview() {
+        let templates = Templates::load("templates").unwrap();
+
+        for locale in Locale::ALL {
+            assert_ok!(templates.render_preview("confirmation", locale));
+            assert_ok!(templates.render_preview("confirmed", locale));
+        }
+    }
+
+    #[test]
+    fn emails_are_rendered_in_the_requested_locale() {
+        let templates = Templates::load("templates").unwrap();
+
+        let email = templates
+            .render("confirmation", Locale::French, &confirmation_variables())
+            .unwrap();
+
+        assert_eq!(email.subject, "Bienvenue");
+        assert!(email.text_body.contains("Bonjour Ursula"));
+    }
+
+    #[test]
+    fn untranslated_templates_fall_back_to_english() {
+        let directory = templates_copy();
+        std::fs::remove_dir_all(directory.path().join("de")).unwrap();
+        let templates = Templates::load(directory.path()).unwrap();
+
+        let email = templates
+            .render("confirmation", Locale::German, &confirmation_variables())
+            .unwrap();
+        let page = templates
+            .render_page(
+                "confirmed",
+                Locale::German,
+                &variables(&[("name", "Ursula")]),
+            )
+            .unwrap();
+
+        assert_eq!(email.subject, "Welcome");
+        assert!(page.contains(r#"<html lang="en">"#));
+    }
+
+    #[test]
+    fn incomplete_translations_fail_to_load() {
+        let directory = templates_copy();
+        std::fs::remove_file(directory.path().join("fr").join("confirmation.txt")).unwrap();
+
+        let result = Templates::load(directory.path());
+
+        assert!(matches!(result, Err(TemplateError::Read { .. })));
+    }
+
+    #[test]
+    fn pages_preview_as_html() {
+        let templates = Templates::load("templates").unwrap();
+
+        let preview = templates.render_preview("confirmed", Locale::English);
+
+        assert!(matches!(preview, Ok(Preview::Page(html)) if html.contains("Ursula Le Guin")));
+    }
+
+    #[test]
+    fn templates_using_unknown_variables_fail_to_load() {
+        let directory = templates_copy();
+        std::fs::write(
+            directory.path().join("en").join("confirmation.txt"),
+            "Hi {{surname}}",
+        )
+        .unwrap();
+
+        let result = Templates::load(directory.path());
+
+        assert!(matches!(
+            result,
+            Err(TemplateError::UnknownVariable { variable, .. }) if variable == "surname"
+        ));
+    }
+
+    #[test]
+    fn unsupported_issue_css_fails_to_load() {
+        let directory = templates_copy();
+        std::fs::write(
+            directory.path().join("issue.css"),
+            "@import url(fonts.css);",
+        )
+        .unwrap();
+
+        let result = Templates::load(directory.path());
+
+        assert!(matches!(result, Err(TemplateError::Syntax { .. })));
+    }
+
+    #[test]
+    fn missing_templates_fail_to_load() {
+        let directory = templates_copy();
+        std::fs::remove_file(directory.path().join("en").join("confirmation.html")).unwrap();
+
+        let result = Templates::load(directory.path());
+
+        assert!(matches!(result, Err(TemplateError::Read { .. })));
+    }
+
+    #[test]
+    fn previews_of_unknown_templates_fail() {
+        let templates = Templates::load("templates").unwrap();
+
+        let result = templates.render_preview("farewell", Locale::English);
+
+        assert!(matches!(result, Err(TemplateError::NotFound(_))));
+    }
+
+    #[test]
+    fn defaults_are_used_for_missing_or_empty_values() {
+        let template =
+            Template::parse(r#"Hi {{ name | default: "friend" }}"#, Escape::None).unwrap();
+
+        let missing = template.render(&variables(&[]));
+        let empty = template.render(&variables(&[("name", "")]));
+        let set = template.render(&variables(&[("name", "Ursula")]));
+
+        assert_eq!(missing.unwrap(), "Hi friend");
+        assert_eq!(empty.unwrap(), "Hi friend");
+        assert_eq!(set.unwrap(), "Hi Ursula");
+    }
+
+    #[test]
+    fn defaults_can_use_single_quotes() {
+        let template = Template::parse("{{ name | default: 'friend' }}", Escape::None).unwrap();
+
+        assert_eq!(template.render(&variables(&[])).unwrap(), "friend");
+    }
+
+    #[test]
+    fn malformed_filters_are_rejected() {
+        for source in [
+            "{{ name | upcase }}",
+            "{{ name | default }}",
+            "{{ name | default: friend }}",
+            r#"{{ name | default: "friend }}"#,
+        ] {
+            assert_err!(Template::parse(source, Escape::None));
+        }
+    }
+
+    #[test]
+    fn issues_are_personalized_for_every_recipient() {
+        let issue = IssueTemplate::parse(
+            "News for {{ name }}",
+            r#"<p>Hi {{ name }}, you're on the {{ attributes.plan | default: "free" }} plan.</p>
+<a href="{{ unsubscribe_link }}">Unsubscribe</a>"#,
+            "Subscribed as {{ email }} since {{ subscribed_at }}",
+        )
+        .unwrap();
+
+        let free = issue.render(&recipient(&[])).unwrap();
+        let pro = issue.render(&recipient(&[("plan", "pro")])).unwrap();
+
+        assert_eq!(free.subject, "News for Ursula");
+        assert!(free.html_body.contains("you're on the free plan"));
+        assert!(free
+            .html_body
+            .contains(r#"href="https://example.com/unsubscribe?token=abc""#));
+        assert!(pro.html_body.contains("on the pro plan"));
+        assert_eq!(
+            free.text_body,
+            "Subscribed as ursula_le_guin@gmail.com since October 18, 2026"
+        );
+    }
+
+    #[test]
+    fn issues_using_unknown_variables_are_rejected() {
+        let result = IssueTemplate::parse("News", "<p>Hi {{ first_name }}</p>", "Hi");
+
+        assert!(matches!(
+            result,
+            Err(TemplateError::UnknownVariable { variable, .. }) if variable == "first_name"
+        ));
+    }
+
+    #[test]
+    fn issues_using_custom_attributes_without_a_default_are_rejected() {
+        let result = IssueTemplate::parse("News", "<p>Hi</p>", "{{ attributes.plan }}");
+
+        assert!(matches!(result, Err(TemplateError::Syntax { .. })));
+    }
+}
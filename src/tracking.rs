@@ -0,0 +1,382 @@
+use chrono::{DateTime, Duration, Utc};
+use lol_html::{element, html_content::ContentType, rewrite_str, RewriteStrSettings};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::signing::{self, Purpose, SigningError};
+
+/// How long tracking links keep working after the issue went out
+const TOKEN_LIFETIME_DAYS: i64 = 180;
+
+/// Something a subscriber did with a newsletter issue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TrackingEvent {
+    /// The subscriber's mail client loaded the tracking pixel
+    Open,
+    /// The subscriber followed a link to `url`
+    Click { url: String },
+}
+
+impl TrackingEvent {
+    /// The value stored in the `kind` column of `tracking_events`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Click { .. } => "click",
+        }
+    }
+}
+
+/// Everything a tracking endpoint needs to record an event. Handed out inside
+/// tracking URLs as a signed token, so it can't be forged or altered. In particular,
+/// nobody can turn our click redirect into an open redirect.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackingToken {
+    pub subscriber_id: Uuid,
+    pub issue_id: Uuid,
+    #[serde(flatten)]
+    pub event: TrackingEvent,
+    /// Tokens are only accepted for `TOKEN_LIFETIME_DAYS` after this
+    pub issued_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TrackingTokenError {
+    #[error("The tracking token is malformed")]
+    Malformed,
+    #[error("The tracking token's signature is invalid")]
+    InvalidSignature,
+    #[error("The tracking token has expired")]
+    Expired,
+}
+
+impl TrackingToken {
+    /// Serializes the token and signs it with `secret`. The result is URL safe.
+    pub fn sign(&self, secret: &Secret<String>) -> String {
+        signing::sign(Purpose::Tracking, self, secret)
+    }
+
+    /// Parses a token produced by `sign`, checking that it was signed with `secret`,
+    /// hasn't been tampered with since and hasn't expired.
+    pub fn verify(token: &str, secret: &Secret<String>) -> Result<Self, TrackingTokenError> {
+        Self::verify_at(token, secret, Utc::now())
+    }
+
+    fn verify_at(
+        token: &str,
+        secret: &Secret<String>,
+        now: DateTime<Utc>,
+    ) -> Result<Self, TrackingTokenError> {
+        let token: Self =
+            signing::verify(Purpose::Tracking, token, secret).map_err(|err| match err {
+                SigningError::Malformed => TrackingTokenError::Malformed,
+                SigningError::InvalidSignature => TrackingTokenError::InvalidSignature,
+            })?;
+        if now - token.issued_at > Duration::days(TOKEN_LIFETIME_DAYS) {
+            return Err(TrackingTokenError::Expired);
+        }
+        Ok(token)
+    }
+}
+
+/// Adds open and click tracking to the HTML body of a newsletter issue, for one
+/// particular subscriber.
+///
+/// Every `http(s)` link is replaced by a link to our `/t/c/{token}` redirect, and a
+/// `/t/o/{token}.gif` pixel is added to the end of the body. `base_url` is the base
+/// URL the app is reachable at.
+///
+/// Nothing sends issues yet, so only the endpoints are live. Whatever sends them
+/// should call this on every recipient's rendered HTML.
+pub fn add_tracking(
+    html: &str,
+    base_url: &str,
+    subscriber_id: Uuid,
+    issue_id: Uuid,
+    secret: &Secret<String>,
+) -> Result<String, lol_html::errors::RewritingError> {
+    let issued_at = Utc::now();
+    let token = |event| {
+        TrackingToken {
+            subscriber_id,
+            issue_id,
+            event,
+            issued_at,
+        }
+        .sign(secret)
+    };
+    let pixel = format!(
+        r#"<img src="{}/t/o/{}.gif" width="1" height="1" alt="" style="display:none">"#,
+        base_url,
+        token(TrackingEvent::Open)
+    );
+    let mut has_body = false;
+
+    let tracked = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("a[href]", |el| {
+                    // lol_html hands us the attribute as written in the source
+                    let url = decode_entities(&el.get_attribute("href").unwrap());
+                    if url.starts_with("http://") || url.starts_with("https://") {
+                        let tracked_url =
+                            format!("{}/t/c/{}", base_url, token(TrackingEvent::Click { url }));
+                        el.set_attribute("href", &tracked_url)?;
+                    }
+                    Ok(())
+                }),
+                element!("body", |el| {
+                    has_body = true;
+                    el.append(&pixel, ContentType::Html);
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )?;
+
+    if has_body {
+        Ok(tracked)
+    } else {
+        Ok(tracked + &pixel)
+    }
+}
+
+/// Decodes the character references in an HTML attribute value: numeric ones like
+/// `&#38;` and `&#x26;`, and the named ones that turn up in URLs. Anything else is
+/// left as written, like browsers do with references they don't know.
+fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest[1..].find(';').and_then(|end| {
+            let c = decode_reference(&rest[1..end + 1])?;
+            Some((c, end + 2))
+        });
+        match reference {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The character `reference` (without `&` and `;`) stands for.
+fn decode_reference(reference: &str) -> Option<char> {
+    if let Some(number) = reference.strip_prefix('#') {
+        let (digits, radix) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16),
+            None => (number, 10),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return None;
+        }
+        // Too many digits is out of range too
+        let code_point = u32::from_str_radix(digits, radix).unwrap_or(u32::MAX);
+        // NUL, surrogates and values out of range stand for the replacement character
+        return Some(
+            char::from_u32(code_point)
+                .filter(|c| *c != '\0')
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        );
+    }
+    match reference {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        "num" => Some('#'),
+        "percnt" => Some('%'),
+        "quest" => Some('?'),
+        "equals" => Some('='),
+        "sol" => Some('/'),
+        "colon" => Some(':'),
+        "semi" => Some(';'),
+        "plus" => Some('+'),
+        "commat" => Some('@'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use chrono::{Duration, Utc};
+
+    use super::{add_tracking, decode_entities, TrackingEvent, TrackingToken};
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    fn click_token() -> TrackingToken {
+        TrackingToken {
+            subscriber_id: Uuid::new_v4(),
+            issue_id: Uuid::new_v4(),
+            event: TrackingEvent::Click {
+                url: "https://example.com/article?id=1".to_string(),
+            },
+            issued_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn a_signed_token_can_be_verified() {
+        let token = click_token();
+
+        let signed = token.sign(&secret());
+
+        assert_eq!(TrackingToken::verify(&signed, &secret()).unwrap(), token);
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let signed = click_token().sign(&Secret::new("another-key".to_string()));
+
+        assert_err!(TrackingToken::verify(&signed, &secret()));
+    }
+
+    #[test]
+    fn a_token_with_a_swapped_payload_is_rejected() {
+        let signed = click_token().sign(&secret());
+        let (_, signature) = signed.split_once('.').unwrap();
+        let mut evil = click_token();
+        evil.event = TrackingEvent::Click {
+            url: "https://evil.com".to_string(),
+        };
+        let evil_signed = evil.sign(&secret());
+        let (evil_payload, _) = evil_signed.split_once('.').unwrap();
+
+        let forged = format!("{}.{}", evil_payload, signature);
+
+        assert_err!(TrackingToken::verify(&forged, &secret()));
+    }
+
+    #[test]
+    fn tokens_expire() {
+        let signed = click_token().sign(&secret());
+
+        assert_ok!(TrackingToken::verify_at(
+            &signed,
+            &secret(),
+            Utc::now() + Duration::days(179)
+        ));
+        assert!(matches!(
+            TrackingToken::verify_at(&signed, &secret(), Utc::now() + Duration::days(181)),
+            Err(super::TrackingTokenError::Expired)
+        ));
+    }
+
+    #[test]
+    fn character_references_are_decoded() {
+        assert_eq!(
+            decode_entities("https://example.com/?a=1&amp;b=2&#38;c=3&#x26;d=&quot;4&quot;"),
+            r#"https://example.com/?a=1&b=2&c=3&d="4""#
+        );
+        assert_eq!(
+            decode_entities("&#0;&#xD800;&#x110000;"),
+            "\u{fffd}".repeat(3)
+        );
+    }
+
+    #[test]
+    fn unknown_references_and_bare_ampersands_are_kept() {
+        for value in [
+            "?a=1&b=2",
+            "?a=1&unknown;",
+            "&",
+            "&;",
+            "&#;",
+            "&#xZZ;",
+            "a&b c;",
+        ] {
+            assert_eq!(decode_entities(value), value, "{}", value);
+        }
+    }
+
+    #[test]
+    fn garbage_tokens_are_rejected() {
+        for token in ["", "no-dot", "not base64.at all", "e30.e30"] {
+            assert_err!(TrackingToken::verify(token, &secret()));
+        }
+    }
+
+    #[test]
+    fn add_tracking_rewrites_web_links_only() {
+        let html = r#"<body><a href="https://example.com/?a=1&amp;b=2">Read</a> <a href="mailto:me@example.com">Mail</a></body>"#;
+
+        let tracked = add_tracking(
+            html,
+            "http://127.0.0.1",
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            &secret(),
+        )
+        .unwrap();
+
+        assert!(tracked.contains(r#"href="mailto:me@example.com""#));
+        let token = tracked
+            .split(r#"href="http://127.0.0.1/t/c/"#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        let token = assert_ok!(TrackingToken::verify(token, &secret()));
+        assert_eq!(
+            token.event,
+            TrackingEvent::Click {
+                url: "https://example.com/?a=1&b=2".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn add_tracking_appends_a_pixel_inside_the_body() {
+        let html = "<html><body><p>Hello</p></body></html>";
+
+        let tracked = add_tracking(
+            html,
+            "http://127.0.0.1",
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            &secret(),
+        )
+        .unwrap();
+
+        let pixel_at = tracked.find(r#"<img src="http://127.0.0.1/t/o/"#).unwrap();
+        assert!(pixel_at > tracked.find("<p>Hello</p>").unwrap());
+        assert!(pixel_at < tracked.find("</body>").unwrap());
+    }
+
+    #[test]
+    fn add_tracking_appends_a_pixel_to_fragments() {
+        let html = "<p>Hello</p>";
+
+        let tracked = add_tracking(
+            html,
+            "http://127.0.0.1",
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            &secret(),
+        )
+        .unwrap();
+
+        assert!(tracked.starts_with("<p>Hello</p><img"));
+    }
+}
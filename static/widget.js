@@ -0,0 +1,208 @@
+/* Newsletter signup widget.
+ *
+ * Renders a signup box into every element with a `data-newsletter-signup`
+ * attribute. Get a ready-made snippet from `/embed/snippet`, or write one:
+ *
+ *   <div data-newsletter-signup data-source="partner-blog"></div>
+ *   <script src="https://newsletter.example/embed/v2/widget.js" async></script>
+ *
+ * Data attributes, all optional:
+ *   data-source   Where the signups come from: letters, digits, `-` and `_`
+ *   data-locale   `en`, `fr` or `de`. The browser's language if not set.
+ *   data-title, data-button, data-success   Replace the default texts
+ *   data-accent   The color of the button, e.g. `#0a7`
+ */
+(function () {
+  "use strict";
+
+  var script = document.currentScript;
+  // Takes form posts, which browsers send from any page without a CORS preflight
+  var apiUrl = new URL("/embed/subscriptions", script.src).href;
+
+  var texts = {
+    en: {
+      title: "Subscribe to our newsletter",
+      name: "Name",
+      email: "Email",
+      button: "Subscribe",
+      success: "Thanks! Check your inbox to confirm your subscription.",
+      error: "Something went wrong, please try again later.",
+      suggestion: "Did you mean {email}?",
+      accept: "Yes",
+      decline: "No, keep mine"
+    },
+    fr: {
+      title: "Abonnez-vous à notre newsletter",
+      name: "Nom",
+      email: "E-mail",
+      button: "S'abonner",
+      success: "Merci ! Consultez votre boîte de réception pour confirmer votre abonnement.",
+      error: "Une erreur est survenue, veuillez réessayer plus tard.",
+      suggestion: "Vouliez-vous dire {email} ?",
+      accept: "Oui",
+      decline: "Non, garder la mienne"
+    },
+    de: {
+      title: "Abonnieren Sie unseren Newsletter",
+      name: "Name",
+      email: "E-Mail",
+      button: "Abonnieren",
+      success: "Danke! Bitte bestätigen Sie Ihr Abonnement über den Link in Ihrem Postfach.",
+      error: "Etwas ist schiefgelaufen, bitte versuchen Sie es später erneut.",
+      suggestion: "Meinten Sie {email}?",
+      accept: "Ja",
+      decline: "Nein, meine behalten"
+    }
+  };
+
+  var styles =
+    ":host { all: initial; display: block; }" +
+    ".box { font: 15px/1.4 system-ui, sans-serif; color: #222; background: #fff;" +
+    "  border: 1px solid #ddd; border-radius: 8px; padding: 16px; max-width: 420px; }" +
+    "h2 { font-size: 18px; margin: 0 0 12px; }" +
+    "label { display: block; margin-bottom: 8px; }" +
+    "input { box-sizing: border-box; width: 100%; padding: 8px; margin-top: 4px;" +
+    "  border: 1px solid #bbb; border-radius: 4px; font: inherit; }" +
+    "button { padding: 8px 16px; border: 0; border-radius: 4px; color: #fff;" +
+    "  background: var(--accent, #0a7); font: inherit; cursor: pointer; }" +
+    "button:disabled { opacity: 0.6; cursor: default; }" +
+    ".status { margin: 8px 0 0; }" +
+    ".status button { margin-left: 8px; padding: 4px 10px; }" +
+    ".status.error { color: #b00020; }" +
+    ".status.success { color: #0a6e3c; }";
+
+  function pickTexts(container) {
+    var requested = container.getAttribute("data-locale") || navigator.language || "en";
+    var locale = requested.split(/[-_]/)[0].toLowerCase();
+    var defaults = texts[locale] || texts.en;
+    var picked = { locale: texts[locale] ? locale : "en" };
+    Object.keys(defaults).forEach(function (key) {
+      picked[key] = container.getAttribute("data-" + key) || defaults[key];
+    });
+    return picked;
+  }
+
+  function element(tag, properties, children) {
+    var el = document.createElement(tag);
+    Object.keys(properties || {}).forEach(function (key) {
+      el[key] = properties[key];
+    });
+    (children || []).forEach(function (child) {
+      el.appendChild(child);
+    });
+    return el;
+  }
+
+  function render(container) {
+    if (container.hasAttribute("data-newsletter-rendered")) {
+      return;
+    }
+    container.setAttribute("data-newsletter-rendered", "");
+
+    var text = pickTexts(container);
+    var root = container.attachShadow ? container.attachShadow({ mode: "open" }) : container;
+    var name = element("input", { type: "text", name: "name", required: true, autocomplete: "name" });
+    var email = element("input", { type: "email", name: "email", required: true, autocomplete: "email" });
+    var button = element("button", { type: "submit", textContent: text.button });
+    var status = element("p", { className: "status" });
+    status.setAttribute("role", "status");
+    var form = element("form", {}, [
+      element("label", { textContent: text.name }, [name]),
+      element("label", { textContent: text.email }, [email]),
+      button
+    ]);
+    var box = element("div", { className: "box" }, [
+      element("h2", { textContent: text.title }),
+      form,
+      status
+    ]);
+    var accent = container.getAttribute("data-accent");
+    if (accent) {
+      box.style.setProperty("--accent", accent);
+    }
+    root.appendChild(element("style", { textContent: styles }));
+    root.appendChild(box);
+
+    function show(kind, message) {
+      status.className = "status " + kind;
+      status.textContent = message;
+    }
+
+    // Asks whether the address was meant to be `suggestion`, it looked misspelled
+    function offer(suggestion) {
+      var accept = element("button", { type: "button", textContent: text.accept });
+      var decline = element("button", { type: "button", textContent: text.decline });
+      accept.addEventListener("click", function () {
+        email.value = suggestion;
+        submit(false);
+      });
+      decline.addEventListener("click", function () {
+        submit(true);
+      });
+      show("", text.suggestion.replace("{email}", suggestion));
+      status.appendChild(accept);
+      status.appendChild(decline);
+    }
+
+    function submit(keepEmail) {
+      button.disabled = true;
+      show("", "");
+      var body = new URLSearchParams({
+        name: name.value,
+        email: email.value,
+        locale: text.locale,
+        keep_email: keepEmail,
+        suggestions: true
+      });
+      var source = container.getAttribute("data-source");
+      if (source) {
+        body.append("source", source);
+      }
+      // No custom headers, so the request stays a simple one
+      fetch(apiUrl, { method: "POST", body: body })
+        .then(function (response) {
+          return response
+            .json()
+            .catch(function () {
+              return {};
+            })
+            .then(function (body) {
+              if (response.status === 409 && body.suggestion) {
+                return body.suggestion;
+              }
+              if (!response.ok) {
+                throw new Error(body.error || text.error);
+              }
+            });
+        })
+        .then(function (suggestion) {
+          if (suggestion) {
+            button.disabled = false;
+            offer(suggestion);
+            return;
+          }
+          form.hidden = true;
+          show("success", text.success);
+        })
+        .catch(function (error) {
+          button.disabled = false;
+          show("error", error instanceof TypeError ? text.error : error.message);
+        });
+    }
+
+    form.addEventListener("submit", function (event) {
+      event.preventDefault();
+      submit(false);
+    });
+  }
+
+  function renderAll() {
+    Array.prototype.forEach.call(document.querySelectorAll("[data-newsletter-signup]"), render);
+  }
+
+  if (document.readyState === "loading") {
+    document.addEventListener("DOMContentLoaded", renderAll);
+  } else {
+    renderAll();
+  }
+})();
@@ -1,10 +1,16 @@
+use std::sync::Arc;
+
 use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use url::Url;
 use uuid::Uuid;
 use wiremock::MockServer;
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{get_configuration, DatabaseSettings, Settings},
+    email_client::EmailClient,
+    outbox::{try_relay_email, ExecutionOutcome},
+    routes::PostmarkWebhookOptions,
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -34,13 +40,96 @@ pub struct TestApp {
     /// Pool to use for DB connections in testing
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    /// The client the app sends email with
+    pub email_client: Arc<EmailClient>,
+    /// Key the app signs tokens with
+    pub hmac_secret: Secret<String>,
+    /// Credentials the app expects on Postmark webhook calls
+    pub postmark_webhook: PostmarkWebhookOptions,
 }
 
 impl TestApp {
+    /// Relays every email that is due from the outbox, like the background relay
+    /// would
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            let outcome = try_relay_email(&self.db_pool, &self.email_client)
+                .await
+                .expect("Failed to relay outbox email");
+            if outcome == ExecutionOutcome::EmptyQueue {
+                break;
+            }
+        }
+    }
+
     /// Send a POST with `body` to the subscriptions API of our mocked app
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscribe", self.address))
+            .post(format!("{}/subscribe", self.address))
+            .header("Content-type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Send a POST with `body` to the subscriptions API of our mocked app, from a
+    /// browser preferring the languages in `accept_language`
+    pub async fn post_subscriptions_with_language(
+        &self,
+        body: String,
+        accept_language: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscribe", self.address))
+            .header("Content-type", "application/x-www-form-urlencoded")
+            .header("Accept-Language", accept_language)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Send a POST with the JSON `body` to the subscriptions API, like the widget
+    pub async fn post_subscription_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Send a form post with `body` to the widget's endpoint, from a page at `origin`
+    pub async fn post_embedded_subscription(&self, body: &str, origin: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/embed/subscriptions", self.address))
+            .header("Origin", origin)
+            .header("Content-type", "application/x-www-form-urlencoded")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Load the subscription form with `browser`
+    pub async fn get_subscription_form(&self, browser: &reqwest::Client) -> reqwest::Response {
+        browser
+            .get(format!("{}/subscribe", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Submit the subscription form with `body` from `browser`, without following the
+    /// redirect back to the form
+    pub async fn post_subscription_form(
+        &self,
+        browser: &reqwest::Client,
+        body: String,
+    ) -> reqwest::Response {
+        browser
+            .post(format!("{}/subscribe", self.address))
             .header("Content-type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -51,7 +140,34 @@ impl TestApp {
     /// Send a GET request to confirm a newsletter subscription
     pub async fn get_subscription_confirmation(&self) -> reqwest::Response {
         reqwest::Client::new()
-            .get(&format!("{}/subscriptions/confirm", &self.address))
+            .get(format!("{}/subscriptions/confirm", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Send a GET to preview the template called `name`, with the given query string
+    pub async fn get_template_preview(&self, name: &str, query: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/templates/{}/preview?{}",
+                &self.address, name, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Send a Postmark webhook call with `body` to our mocked app, authenticating with
+    /// the credentials the app was configured with
+    pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.postmark_webhook.username,
+                Some(self.postmark_webhook.password.expose_secret()),
+            )
+            .json(body)
             .send()
             .await
             .expect("Failed to execute request")
@@ -60,7 +176,7 @@ impl TestApp {
     /// Send a GET to the health_check API of our mocked app
     pub async fn get_health_check(&self) -> reqwest::Response {
         reqwest::Client::new()
-            .get(&format!("{}/health_check", &self.address))
+            .get(format!("{}/health_check", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -79,7 +195,7 @@ impl TestApp {
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str();
 
-            let mut confirmation_link = Url::parse(&raw_link).unwrap();
+            let mut confirmation_link = Url::parse(raw_link).unwrap();
             // our tests should not be hitting real APIs out in the world
             assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
             // Because of the way our test framework is set up, our fake base URL doesn't
@@ -91,12 +207,22 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { plain_text, html }
     }
 }
 
+/// A client that keeps cookies like a browser, and hands us redirects instead of
+/// following them
+pub fn browser() -> reqwest::Client {
+    reqwest::Client::builder()
+        .cookie_store(true)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
 /// The confirmation links included in a request through our email client
 pub struct ConfirmationLinks {
     /// Link in the plain text email
@@ -108,6 +234,12 @@ pub struct ConfirmationLinks {
 /// Spins up a testing app to write integration tests against.
 /// Returns the address to connect to.
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spins up a testing app like `spawn_app`, letting `configure` change its settings
+/// first.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // TRACING will only run the first time this function is called.
     Lazy::force(&TRACING);
 
@@ -120,7 +252,14 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         // Ask the OS for a random port
         c.application.port = 0;
-        c.email_client.base_url = email_server.uri();
+        c.email_client.providers[0].base_url = email_server.uri();
+        // Failed sends stay in the outbox, no need to wait on retries
+        c.email_client.max_retries = 0;
+        // Our tests fill in forms a lot faster than people
+        c.application.minimum_seconds_to_submit = 0;
+        // Most tests post the bare form, like the forms hosted elsewhere do
+        c.application.legacy_form_posts = true;
+        configure(&mut c);
 
         c
     };
@@ -131,14 +270,24 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application");
     let port = app.port();
+    let email_client = app.email_client();
     let address = format!("http://127.0.0.1:{}", port);
-    let _ = tokio::spawn(app.run_until_stopped());
+    tokio::spawn(app.run_until_stopped());
 
     TestApp {
         address,
         port,
         db_pool: get_connection_pool(&configuration.database),
         email_server,
+        email_client,
+        hmac_secret: configuration
+            .application
+            .hmac_secret()
+            .expect("Failed to look up the HMAC secret"),
+        postmark_webhook: configuration
+            .postmark_webhook
+            .options()
+            .expect("Failed to look up the Postmark webhook password"),
     }
 }
 
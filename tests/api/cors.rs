@@ -0,0 +1,92 @@
+use zero2prod::configuration::Settings;
+
+use crate::app::{self, TestApp};
+
+async fn spawn_app_for_partners() -> TestApp {
+    app::spawn_app_with(|c: &mut Settings| {
+        c.application.cors.allowed_origins = vec![
+            "https://partner.example".to_string(),
+            "https://*.friends.example".to_string(),
+        ];
+    })
+    .await
+}
+
+async fn preflight(app: &TestApp, path: &str, origin: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .request(reqwest::Method::OPTIONS, format!("{}{}", app.address, path))
+        .header("Origin", origin)
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "content-type")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[actix_web::test]
+async fn allowed_origins_pass_the_preflight_for_subscribe() {
+    let app = spawn_app_for_partners().await;
+
+    for origin in ["https://partner.example", "https://www.friends.example"] {
+        let response = preflight(&app, "/subscribe", origin).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], origin);
+        assert_eq!(response.headers()["Access-Control-Max-Age"], "3600");
+    }
+}
+
+#[actix_web::test]
+async fn subscriptions_from_allowed_origins_get_cors_headers() {
+    let app = spawn_app_for_partners().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscribe", app.address))
+        .header("Origin", "https://partner.example")
+        .header("Content-type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        "https://partner.example"
+    );
+}
+
+#[actix_web::test]
+async fn other_origins_get_no_cors_headers() {
+    let app = spawn_app_for_partners().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscribe", app.address))
+        .header("Origin", "https://friends.example")
+        .header("Content-type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert!(!response
+        .headers()
+        .contains_key("Access-Control-Allow-Origin"));
+}
+
+#[actix_web::test]
+async fn other_endpoints_get_no_cors_headers() {
+    let app = spawn_app_for_partners().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/templates/confirmation/preview", app.address))
+        .header("Origin", "https://partner.example")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!response
+        .headers()
+        .contains_key("Access-Control-Allow-Origin"));
+}
@@ -0,0 +1,111 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::email_log::email_log_for_subscriber;
+
+use crate::app::{self, TestApp};
+
+const MESSAGE_ID: &str = "b7bc2f4a-e38e-4336-af7d-e6c392c2f817";
+
+/// Postmark's response to a successful send
+fn sent() -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "To": "ursula_le_guin@gmail.com",
+        "SubmittedAt": "2026-10-18T12:01:05.1794748-04:00",
+        "MessageID": MESSAGE_ID,
+        "ErrorCode": 0,
+        "Message": "OK"
+    }))
+}
+
+/// Signs up a subscriber and returns their ID
+async fn sign_up(app: &TestApp) -> Uuid {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriber")
+        .id
+}
+
+#[actix_web::test]
+async fn confirmation_emails_are_logged_with_the_provider_message_id() {
+    let app = app::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(sent())
+        .mount(&app.email_server)
+        .await;
+
+    let subscriber_id = sign_up(&app).await;
+
+    let log = email_log_for_subscriber(&app.db_pool, subscriber_id)
+        .await
+        .unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(log[0].purpose, "confirmation");
+    assert_eq!(log[0].provider.as_deref(), Some("postmark"));
+    assert_eq!(log[0].message_id.as_deref(), Some(MESSAGE_ID));
+    assert_eq!(log[0].status, "sent");
+    assert_eq!(log[0].error, None);
+}
+
+#[actix_web::test]
+async fn rejected_confirmation_emails_are_logged_as_failed() {
+    let app = app::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(json!({
+            "ErrorCode": 406,
+            "Message": "Inactive recipient"
+        })))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let saved = sqlx::query!("SELECT status, error FROM email_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch email log");
+    assert_eq!(saved.status, "failed");
+    assert!(saved.error.unwrap().contains("Inactive recipient"));
+}
+
+#[actix_web::test]
+async fn bounces_are_matched_to_the_logged_email() {
+    let app = app::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(sent())
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = sign_up(&app).await;
+
+    app.post_postmark_webhook(&json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": "ursula_le_guin@gmail.com",
+        "MessageID": MESSAGE_ID,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let log = email_log_for_subscriber(&app.db_pool, subscriber_id)
+        .await
+        .unwrap();
+    assert_eq!(log[0].status, "bounced");
+    assert_eq!(log[0].error.as_deref(), Some("HardBounce"));
+}
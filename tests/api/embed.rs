@@ -0,0 +1,145 @@
+use crate::app;
+
+#[actix_web::test]
+async fn the_widget_is_served_with_cache_headers() {
+    let app = app::spawn_app().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/embed/widget.js", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/javascript; charset=utf-8"
+    );
+    assert_eq!(response.headers()["Cache-Control"], "public, max-age=3600");
+    assert_eq!(response.headers()["X-Widget-Version"], "2");
+    let etag = response.headers()["ETag"].clone();
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("data-newsletter-signup"));
+
+    let revalidated = client
+        .get(format!("{}/embed/widget.js", app.address))
+        .header("If-None-Match", etag)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(revalidated.status().as_u16(), 304);
+}
+
+#[actix_web::test]
+async fn only_the_current_version_of_the_widget_is_served_for_good() {
+    let app = app::spawn_app().await;
+
+    let current = reqwest::get(format!("{}/embed/v2/widget.js", app.address))
+        .await
+        .expect("Failed to execute request");
+    let unknown = reqwest::get(format!("{}/embed/v1/widget.js", app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(current.status().as_u16(), 200);
+    assert_eq!(
+        current.headers()["Cache-Control"],
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(unknown.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn the_snippet_embeds_the_versioned_widget() {
+    let app = app::spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/embed/snippet?source=partner-blog&locale=fr",
+        app.address
+    ))
+    .await
+    .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let snippet = response.text().await.unwrap();
+    assert!(snippet
+        .contains(r#"<div data-newsletter-signup data-source="partner-blog" data-locale="fr">"#));
+    assert!(snippet.contains(r#"<script src="http://127.0.0.1/embed/v2/widget.js" async>"#));
+}
+
+#[actix_web::test]
+async fn snippets_with_invalid_sources_are_rejected() {
+    let app = app::spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/embed/snippet?source=%22%3E%3Cscript%3E",
+        app.address
+    ))
+    .await
+    .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn the_widget_can_subscribe_from_any_page() {
+    // No origins are allowed by default
+    let app = app::spawn_app().await;
+
+    let response = app
+        .post_embedded_subscription(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&source=partner-blog&keep_email=false&suggestions=true",
+            "https://blog.example",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Access-Control-Allow-Origin"], "*");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT email, source FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.source.as_deref(), Some("partner-blog"));
+}
+
+#[actix_web::test]
+async fn the_widget_gets_suggestions_from_any_page() {
+    let app = app::spawn_app().await;
+
+    let response = app
+        .post_embedded_subscription(
+            "name=le%20guin&email=ursula%40gmial.com&keep_email=false&suggestions=true",
+            "https://blog.example",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["Access-Control-Allow-Origin"], "*");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["suggestion"], "ursula@gmail.com");
+}
+
+#[actix_web::test]
+async fn the_widget_posts_without_a_preflight() {
+    let app = app::spawn_app().await;
+
+    let widget = reqwest::get(format!("{}/embed/widget.js", app.address))
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+
+    // Browsers only skip the preflight for form posts without custom headers
+    assert!(widget.contains("/embed/subscriptions"));
+    assert!(widget.contains("URLSearchParams"));
+    assert!(!widget.contains("headers:"));
+}
@@ -9,3 +9,15 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[actix_web::test]
+async fn health_check_reports_the_email_circuit_state() {
+    let app = app::spawn_app().await;
+
+    let response = app.get_health_check().await;
+
+    assert_eq!(
+        response.headers()["X-Email-Circuit-State"],
+        "postmark=closed"
+    );
+}
@@ -1,4 +1,14 @@
 mod app;
+mod cors;
+mod email_log;
+mod embed;
 mod health_check;
+mod outbox;
+mod request_limits;
+mod subscription_form;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppression;
+mod templates;
+mod tracking;
+mod webhooks;
@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use wiremock::{
+    matchers::{any, body_partial_json, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::{
+    email_log::EmailPurpose,
+    outbox::{enqueue_email, try_relay_email, ExecutionOutcome, OutboxEmail},
+};
+
+use crate::app::{self, TestApp};
+
+fn outbox_email(recipient: &str) -> OutboxEmail {
+    OutboxEmail {
+        recipient: recipient.into(),
+        subscriber_id: None,
+        subject: "Welcome".into(),
+        html_body: "<p>Hi</p>".into(),
+        text_body: "Hi".into(),
+        purpose: EmailPurpose::Confirmation,
+        tag: None,
+        message_stream: None,
+    }
+}
+
+async fn outbox_length(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count outbox emails")
+        .count
+}
+
+#[actix_web::test]
+async fn outbox_emails_are_sent_and_removed_from_the_outbox() {
+    let app = app::spawn_app().await;
+    enqueue_email(&app.db_pool, &outbox_email("ursula_le_guin@gmail.com"))
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = try_relay_email(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, ExecutionOutcome::TaskCompleted);
+    assert_eq!(outbox_length(&app).await, 0);
+}
+
+#[actix_web::test]
+async fn outbox_emails_are_sent_with_their_tag_and_message_stream() {
+    let app = app::spawn_app().await;
+    let email = OutboxEmail {
+        tag: Some("confirmation".into()),
+        message_stream: Some("outbound".into()),
+        ..outbox_email("ursula_le_guin@gmail.com")
+    };
+    enqueue_email(&app.db_pool, &email).await.unwrap();
+    Mock::given(path("/email"))
+        .and(body_partial_json(serde_json::json!({
+            "Tag": "confirmation",
+            "MessageStream": "outbound"
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = try_relay_email(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, ExecutionOutcome::TaskCompleted);
+}
+
+#[actix_web::test]
+async fn outbox_emails_being_sent_are_claimed_but_not_locked() {
+    let app = app::spawn_app().await;
+    enqueue_email(&app.db_pool, &outbox_email("ursula_le_guin@gmail.com"))
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let relay = try_relay_email(&app.db_pool, &app.email_client);
+    let meanwhile = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let other_relay = try_relay_email(&app.db_pool, &app.email_client)
+            .await
+            .unwrap();
+        let locked = sqlx::query!("SELECT id FROM outbox FOR UPDATE NOWAIT")
+            .fetch_all(&app.db_pool)
+            .await
+            .is_err();
+        (other_relay, locked)
+    };
+    let (outcome, (other_relay, locked)) = tokio::join!(relay, meanwhile);
+
+    assert_eq!(outcome.unwrap(), ExecutionOutcome::TaskCompleted);
+    assert_eq!(other_relay, ExecutionOutcome::EmptyQueue);
+    assert!(!locked);
+}
+
+#[actix_web::test]
+async fn outbox_emails_are_retried_later_while_the_provider_is_unavailable() {
+    let app = app::spawn_app().await;
+    enqueue_email(&app.db_pool, &outbox_email("ursula_le_guin@gmail.com"))
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = try_relay_email(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+    let second = try_relay_email(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+
+    assert_eq!(first, ExecutionOutcome::ProviderUnavailable);
+    // Not due again yet
+    assert_eq!(second, ExecutionOutcome::EmptyQueue);
+    let saved =
+        sqlx::query!(r#"SELECT attempts, next_attempt_at > now() AS "scheduled!" FROM outbox"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.attempts, 1);
+    assert!(saved.scheduled);
+}
+
+#[actix_web::test]
+async fn rejected_outbox_emails_are_kept_as_failed() {
+    let app = app::spawn_app().await;
+    enqueue_email(&app.db_pool, &outbox_email("ursula_le_guin@gmail.com"))
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "Inactive recipient"
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = try_relay_email(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, ExecutionOutcome::TaskCompleted);
+    let saved = sqlx::query!("SELECT failed_at, last_error FROM outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The rejected email was dropped");
+    assert!(saved.failed_at.is_some());
+    assert!(saved.last_error.unwrap().contains("Inactive recipient"));
+    let next = try_relay_email(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+    assert_eq!(next, ExecutionOutcome::EmptyQueue);
+}
+
+#[actix_web::test]
+async fn outbox_emails_to_suppressed_addresses_are_dropped_unsent() {
+    let app = app::spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    sqlx::query!("UPDATE subscriptions SET suppressed_at = now(), suppression_reason = 'manual'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(outbox_length(&app).await, 0);
+}
+
+#[actix_web::test]
+async fn outbox_emails_to_suppressed_subscribers_are_dropped_under_any_spelling() {
+    let app = app::spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let subscriber_id = sqlx::query!(
+        "UPDATE subscriptions SET suppressed_at = now(), suppression_reason = 'manual'
+        RETURNING id"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .id;
+    let to_subscriber = OutboxEmail {
+        subscriber_id: Some(subscriber_id),
+        ..outbox_email("Ursula_Le_Guin@gmail.com")
+    };
+    enqueue_email(&app.db_pool, &to_subscriber).await.unwrap();
+    enqueue_email(&app.db_pool, &outbox_email("ursula_le_guin+news@gmail.com"))
+        .await
+        .unwrap();
+
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(outbox_length(&app).await, 0);
+}
+
+#[actix_web::test]
+async fn an_empty_outbox_is_reported() {
+    let app = app::spawn_app().await;
+
+    let outcome = try_relay_email(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+
+    assert_eq!(outcome, ExecutionOutcome::EmptyQueue);
+}
@@ -0,0 +1,115 @@
+use zero2prod::configuration::{LimitSettings, RequestLimitStore, Settings};
+
+use crate::app;
+
+fn one_per_hour() -> Option<LimitSettings> {
+    Some(LimitSettings {
+        requests: 1,
+        window_seconds: 3600,
+    })
+}
+
+#[actix_web::test]
+async fn subscribing_the_same_address_too_often_is_rejected_with_429() {
+    let app = app::spawn_app_with(|c: &mut Settings| {
+        c.request_limits.subscribe.per_email = one_per_hour();
+    })
+    .await;
+
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let second = app
+        .post_subscriptions("name=le%20guin&email=URSULA_LE_GUIN%40gmail.com".into())
+        .await;
+    let other = app
+        .post_subscriptions("name=tolkien&email=tolkien%40gmail.com".into())
+        .await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+    let retry_after: u64 = second.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=3600).contains(&retry_after));
+    assert_eq!(other.status().as_u16(), 200);
+    let limit_hits = app.get_health_check().await.headers()["X-Request-Limit-Hits"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(limit_hits.contains("subscribe.email=1"), "{}", limit_hits);
+    assert!(limit_hits.contains("subscribe.ip=0"), "{}", limit_hits);
+}
+
+#[actix_web::test]
+async fn the_json_api_shares_the_subscribe_limits() {
+    let app = app::spawn_app_with(|c: &mut Settings| {
+        c.request_limits.subscribe.per_ip = one_per_hour();
+    })
+    .await;
+
+    let _ = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let response = app
+        .post_subscription_json(&serde_json::json!({
+            "name": "tolkien",
+            "email": "tolkien@gmail.com"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+}
+
+#[actix_web::test]
+async fn guessing_confirmation_tokens_is_rate_limited() {
+    let app = app::spawn_app_with(|c: &mut Settings| {
+        c.request_limits.confirm.per_ip = one_per_hour();
+    })
+    .await;
+    let guess = || {
+        reqwest::get(format!(
+            "{}/subscriptions/confirm?subscription_token=guess",
+            app.address
+        ))
+    };
+
+    let first = guess().await.expect("Failed to execute request");
+    let second = guess().await.expect("Failed to execute request");
+
+    assert_eq!(first.status().as_u16(), 401);
+    assert_eq!(second.status().as_u16(), 429);
+    assert!(second.headers().contains_key("Retry-After"));
+}
+
+#[actix_web::test]
+async fn the_postgres_store_shares_counts_through_the_database() {
+    let app = app::spawn_app_with(|c: &mut Settings| {
+        c.request_limits.store = RequestLimitStore::Postgres;
+        c.request_limits.subscribe.global = one_per_hour();
+    })
+    .await;
+
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let second = app
+        .post_subscriptions("name=tolkien&email=tolkien%40gmail.com".into())
+        .await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+    let counted =
+        sqlx::query!("SELECT hits FROM request_counts WHERE key = 'subscribe:global:all'")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch request count");
+    assert_eq!(counted.hits, 2);
+}
@@ -0,0 +1,317 @@
+use crate::app::{self, browser};
+
+/// The value of the hidden field `name` in the form `page`
+fn hidden_field(page: &str, name: &str) -> String {
+    page.split(&format!(r#"name="{}" value=""#, name))
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap_or_else(|| panic!("The page has no {} field", name))
+        .to_string()
+}
+
+/// The value of the hidden CSRF token field in the form `page`
+fn csrf_token(page: &str) -> String {
+    hidden_field(page, "csrf_token")
+}
+
+/// The form `page` submitted with `fields`, along with its hidden fields
+fn form_body(page: &str, fields: &str) -> String {
+    format!(
+        "{}&csrf_token={}&rendered_at={}",
+        fields,
+        csrf_token(page),
+        hidden_field(page, "rendered_at")
+    )
+}
+
+/// Whether anyone was subscribed
+async fn has_subscriptions(app: &app::TestApp) -> bool {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap()
+        .is_some()
+}
+
+#[actix_web::test]
+async fn the_form_is_served_with_a_csrf_token() {
+    let app = app::spawn_app().await;
+
+    let response = app.get_subscription_form(&browser()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|cookie| cookie.name() == "_csrf"));
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<form action="/subscribe" method="post">"#));
+    assert!(!csrf_token(&page).is_empty());
+}
+
+#[actix_web::test]
+async fn submitting_the_form_subscribes_and_shows_the_outcome_once() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let body = form_body(&page, "name=le%20guin&email=ursula_le_guin%40gmail.com");
+
+    let response = app.post_subscription_form(&browser, body).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers()["Location"], "/subscribe?locale=en");
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+
+    let outcome = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(outcome.contains("We sent a confirmation link to ursula_le_guin@gmail.com"));
+    let again = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(again.contains("<form"));
+}
+
+#[actix_web::test]
+async fn validation_errors_are_shown_in_the_form() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let body = form_body(&page, "name=le%20guin&email=definitely-not-an-email");
+
+    let response = app.post_subscription_form(&browser, body).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("definitely-not-an-email is not a valid subscriber email."));
+    assert!(page.contains(r#"value="le guin""#));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[actix_web::test]
+async fn forms_shown_again_after_a_rejection_keep_their_render_timestamp() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let body = form_body(&page, "name=le%20guin&email=definitely-not-an-email");
+
+    app.post_subscription_form(&browser, body).await;
+
+    let rejected = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(
+        hidden_field(&rejected, "rendered_at"),
+        hidden_field(&page, "rendered_at")
+    );
+}
+
+#[actix_web::test]
+async fn policy_rejections_are_shown_in_the_form() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let body = form_body(&page, "name=le%20guin&email=ursula%40yopmail.com");
+
+    app.post_subscription_form(&browser, body).await;
+
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("yopmail.com only hands out throwaway addresses"));
+    assert!(!has_subscriptions(&app).await);
+}
+
+#[actix_web::test]
+async fn misspelled_domains_get_a_suggestion_to_accept_or_turn_down() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let body = form_body(&page, "name=le%20guin&email=ursula%40hotmial.com");
+
+    app.post_subscription_form(&browser, body).await;
+
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("Did you mean ursula@hotmail.com?"));
+    assert!(!has_subscriptions(&app).await);
+
+    // Turning it down
+    let body = form_body(
+        &page,
+        "name=le%20guin&email=ursula%40hotmial.com&keep_email=true",
+    );
+    let response = app.post_subscription_form(&browser, body).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula@hotmial.com");
+}
+
+#[actix_web::test]
+async fn submissions_with_a_forged_csrf_token_are_rejected() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    app.get_subscription_form(&browser).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&csrf_token=forged";
+
+    let response = app.post_subscription_form(&browser, body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("The form has expired"));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[actix_web::test]
+async fn posts_without_a_csrf_token_can_be_turned_off() {
+    let app = app::spawn_app_with(|c| c.application.legacy_form_posts = false).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[actix_web::test]
+async fn submissions_filling_the_honeypot_look_fine_but_are_dropped() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains(r#"name="website""#));
+    let body = form_body(
+        &page,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&website=https%3A%2F%2Fspam.example",
+    );
+
+    let response = app.post_subscription_form(&browser, body).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let outcome = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(outcome.contains("We sent a confirmation link to ursula_le_guin@gmail.com"));
+    assert!(!has_subscriptions(&app).await);
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_web::test]
+async fn submissions_arriving_too_fast_look_fine_but_are_dropped() {
+    let app = app::spawn_app_with(|c| c.application.minimum_seconds_to_submit = 60).await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let body = form_body(&page, "name=le%20guin&email=ursula_le_guin%40gmail.com");
+
+    let response = app.post_subscription_form(&browser, body).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(!has_subscriptions(&app).await);
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_web::test]
+async fn submissions_without_a_render_timestamp_are_dropped() {
+    let app = app::spawn_app().await;
+    let browser = browser();
+    let page = app
+        .get_subscription_form(&browser)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&csrf_token={}&rendered_at=forged",
+        csrf_token(&page)
+    );
+
+    let response = app.post_subscription_form(&browser, body).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(!has_subscriptions(&app).await);
+}
@@ -1,5 +1,4 @@
 use crate::app;
-use sqlx;
 use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
@@ -55,6 +54,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
         .await;
 
     let _ = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     // Mock::expect handles assertion that we sent POST to /email
 }
@@ -71,11 +71,12 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
 
     let _ = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
 
     let confirmation_links = app.get_confirmation_links(email_request);
-    assert_eq!(confirmation_links.html, confirmation_links.html);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
 #[actix_web::test]
@@ -119,3 +120,371 @@ async fn subscribe_returns_a_400_when_fields_are_invalid() {
         );
     }
 }
+
+#[actix_web::test]
+async fn subscribe_leaves_the_confirmation_email_to_the_outbox_relay() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT recipient, subscriber_id, subject FROM outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch outbox email");
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriber");
+    assert_eq!(saved.recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.subscriber_id, Some(subscriber.id));
+    assert_eq!(saved.subject, "Welcome");
+}
+
+#[actix_web::test]
+async fn subscribe_succeeds_while_the_email_provider_is_down() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT attempts, last_error FROM outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The confirmation email was dropped");
+    assert_eq!(saved.attempts, 1);
+    assert!(saved.last_error.is_some());
+}
+
+#[actix_web::test]
+async fn nothing_is_stored_when_the_outbox_write_fails() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    sqlx::query!("ALTER TABLE outbox DROP COLUMN subject")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    let subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.count, 0);
+}
+
+#[actix_web::test]
+async fn subscribe_stores_the_locale_picked_in_the_form() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr";
+
+    app.post_subscriptions_with_language(body.into(), "de")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    let email = sqlx::query!("SELECT subject, text_body FROM outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch outbox email");
+    assert_eq!(saved.locale, "fr");
+    assert_eq!(email.subject, "Bienvenue");
+    assert!(email.text_body.starts_with("Bonjour le guin"));
+}
+
+#[actix_web::test]
+async fn subscribe_falls_back_to_the_browser_language() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    app.post_subscriptions_with_language(body.into(), "es-ES, de-AT;q=0.8, en;q=0.5")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.locale, "de");
+}
+
+#[actix_web::test]
+async fn subscribe_falls_back_to_english_for_unsupported_languages() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=es";
+
+    app.post_subscriptions_with_language(body.into(), "es, pt;q=0.5")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    let email = sqlx::query!("SELECT subject FROM outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch outbox email");
+    assert_eq!(saved.locale, "en");
+    assert_eq!(email.subject, "Welcome");
+}
+
+#[actix_web::test]
+async fn the_json_api_records_where_subscribers_signed_up() {
+    let app = app::spawn_app().await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "source": "partner-blog"
+    });
+
+    let response = app.post_subscription_json(&body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(response["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT email, source FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.source.as_deref(), Some("partner-blog"));
+}
+
+#[actix_web::test]
+async fn the_json_api_explains_rejections() {
+    let app = app::spawn_app().await;
+    let test_cases = [
+        (
+            serde_json::json!({ "name": "le guin", "email": "not-an-email" }),
+            "not-an-email is not a valid subscriber email.",
+        ),
+        (
+            serde_json::json!({
+                "name": "le guin",
+                "email": "ursula_le_guin@gmail.com",
+                "source": "<script>"
+            }),
+            "<script> is not a valid subscription source.",
+        ),
+    ];
+
+    for (body, error) in test_cases {
+        let response = app.post_subscription_json(&body).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        let response: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(response["error"], error);
+    }
+}
+
+#[actix_web::test]
+async fn the_json_api_gives_the_reason_for_policy_rejections() {
+    let app = app::spawn_app().await;
+    let test_cases = [
+        ("ursula@mailinator.com", "disposable_domain"),
+        ("postmaster@example.com", "role_account"),
+    ];
+
+    for (email, reason) in test_cases {
+        let body = serde_json::json!({ "name": "le guin", "email": email });
+
+        let response = app.post_subscription_json(&body).await;
+
+        assert_eq!(response.status().as_u16(), 400, "{}", email);
+        let response: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(response["reason"], reason, "{}", email);
+    }
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[actix_web::test]
+async fn allowlists_keep_other_domains_out() {
+    let app = app::spawn_app_with(|c| {
+        c.application.email_policy.allowed_domains = vec!["example.com".to_string()]
+    })
+    .await;
+
+    let outsider = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let insider = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+
+    assert_eq!(outsider.status().as_u16(), 400);
+    assert_eq!(insider.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn subscribe_keeps_the_spelling_but_normalizes_the_domain() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=Ursula.Le.Guin%40Gmail.COM";
+
+    app.post_subscriptions(body.into()).await;
+
+    let saved = sqlx::query!("SELECT email, canonical_email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "Ursula.Le.Guin@gmail.com");
+    assert_eq!(saved.canonical_email, "ursulaleguin@gmail.com");
+}
+
+#[actix_web::test]
+async fn subscribing_again_under_another_spelling_adds_no_subscriber() {
+    let app = app::spawn_app().await;
+
+    for email in [
+        "u.rsula_le_guin%40gmail.com",
+        "Ursula_Le_Guin%40GMAIL.com",
+        "ursula_le_guin%2Bnews%40googlemail.com",
+        "u.rsula_le_guin%40gmail.com",
+    ] {
+        let body = format!("name=le%20guin&email={}", email);
+
+        let response = app.post_subscriptions(body).await;
+
+        assert_eq!(response.status().as_u16(), 200, "{}", email);
+    }
+    let subscribers = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers, 1);
+}
+
+#[actix_web::test]
+async fn subscribing_again_before_confirming_sends_another_confirmation_email() {
+    let app = app::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    for email in [
+        "ursula_le_guin%40gmail.com",
+        "Ursula_Le_Guin%2Bnews%40gmail.com",
+    ] {
+        let body = format!("name=le%20guin&email={}", email);
+        app.post_subscriptions(body).await;
+    }
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let first = app.get_confirmation_links(&requests[0]).html;
+    let second = app.get_confirmation_links(&requests[1]).html;
+    assert_ne!(first, second);
+    let subscribers = sqlx::query_scalar!(
+        r#"SELECT count(DISTINCT subscriber_id) AS "subscribers!" FROM subscription_tokens"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(subscribers, 1);
+}
+
+#[actix_web::test]
+async fn subscribing_again_once_confirmed_sends_no_email() {
+    let app = app::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_link = app.get_confirmation_links(&requests[0]).html;
+    reqwest::get(confirmation_link).await.unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn the_json_api_suggests_corrections_for_misspelled_domains() {
+    let app = app::spawn_app().await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula@gmial.com",
+        "suggestions": true
+    });
+
+    let response = app.post_subscription_json(&body).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    let response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(response["suggestion"], "ursula@gmail.com");
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[actix_web::test]
+async fn the_json_api_makes_no_suggestions_to_clients_not_asking_for_them() {
+    let app = app::spawn_app().await;
+    let body = serde_json::json!({ "name": "le guin", "email": "ursula@gmial.com" });
+
+    let response = app.post_subscription_json(&body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(response["status"], "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn the_json_api_accepts_addresses_kept_despite_a_suggestion() {
+    let app = app::spawn_app().await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula@gmial.com",
+        "keep_email": true,
+        "suggestions": true
+    });
+
+    let response = app.post_subscription_json(&body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula@gmial.com");
+}
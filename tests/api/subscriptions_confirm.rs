@@ -3,7 +3,23 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::app;
+use crate::app::{self, TestApp};
+
+/// Subscribes le guin and returns the HTML confirmation link they were emailed
+async fn subscribe(app: &TestApp) -> reqwest::Url {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let _ = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request).html
+}
 
 #[actix_web::test]
 async fn confirmations_without_token_are_rejected_with_400() {
@@ -25,6 +41,7 @@ async fn the_link_returned_by_subscribe_returns_200_when_called() {
         .await;
 
     let _ = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
@@ -47,6 +64,7 @@ async fn clicking_on_confirmation_link_confirms_a_subscriber() {
         .await;
 
     let _ = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
@@ -64,3 +82,172 @@ async fn clicking_on_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[actix_web::test]
+async fn the_confirmation_page_is_in_the_subscriber_language() {
+    let app = app::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let _ = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let response = reqwest::get(confirmation_links.html)
+        .await
+        .expect("Failed to execute confirmation request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<html lang="fr">"#));
+    assert!(page.contains("Merci, le guin !"));
+}
+
+#[actix_web::test]
+async fn unknown_tokens_get_an_explanation_page() {
+    let app = app::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=nope",
+            app.address
+        ))
+        .header("Accept-Language", "de")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 401);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<html lang="de">"#));
+}
+
+#[actix_web::test]
+async fn confirmation_links_only_work_once() {
+    let app = app::spawn_app().await;
+    let confirmation_link = subscribe(&app).await;
+    reqwest::get(confirmation_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link)
+        .await
+        .expect("Failed to execute confirmation request");
+
+    assert_eq!(response.status().as_u16(), 401);
+    let tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 0);
+}
+
+#[actix_web::test]
+async fn confirmation_links_do_not_resubscribe_subscribers_who_left() {
+    let app = app::spawn_app().await;
+    let confirmation_link = subscribe(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link)
+        .await
+        .expect("Failed to execute confirmation request");
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[actix_web::test]
+async fn expired_links_do_not_confirm_the_subscriber() {
+    let app = app::spawn_app().await;
+    let confirmation_link = subscribe(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '73 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link)
+        .await
+        .expect("Failed to execute confirmation request");
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("<html"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn confirmed_subscribers_are_redirected_to_the_success_url() {
+    let app = app::spawn_app_with(|c| {
+        c.application.confirm_success_url = Some("https://example.com/welcome".into());
+    })
+    .await;
+    let confirmation_link = subscribe(&app).await;
+
+    let response = app::browser()
+        .get(confirmation_link)
+        .send()
+        .await
+        .expect("Failed to execute confirmation request");
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/welcome"
+    );
+}
+
+#[actix_web::test]
+async fn failures_are_redirected_to_the_failure_url_with_a_reason() {
+    let app = app::spawn_app_with(|c| {
+        c.application.confirm_failure_url = Some("https://example.com/oops?from=email".into());
+    })
+    .await;
+    let confirmation_link = subscribe(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '73 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let expired = app::browser()
+        .get(confirmation_link)
+        .send()
+        .await
+        .expect("Failed to execute confirmation request");
+    let invalid = app::browser()
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=nope",
+            app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute confirmation request");
+
+    assert_eq!(expired.status().as_u16(), 303);
+    assert_eq!(
+        expired.headers()["Location"],
+        "https://example.com/oops?from=email&reason=expired"
+    );
+    assert_eq!(invalid.status().as_u16(), 303);
+    assert_eq!(
+        invalid.headers()["Location"],
+        "https://example.com/oops?from=email&reason=invalid"
+    );
+}
@@ -0,0 +1,43 @@
+use zero2prod::{
+    domain::SubscriberEmail, email_message::EmailMessage, suppression::UnsuppressedMessages,
+};
+
+use crate::app;
+
+fn message(recipient: &str) -> EmailMessage {
+    EmailMessage::new(
+        SubscriberEmail::parse(recipient.to_string()).unwrap(),
+        "Issue #1",
+        "<p>Hi</p>",
+        "Hi",
+    )
+}
+
+#[actix_web::test]
+async fn messages_to_suppressed_subscribers_are_left_out_of_batches() {
+    let app = app::spawn_app().await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.post_subscriptions("name=butler&email=octavia_butler%40gmail.com".into())
+        .await;
+    sqlx::query!(
+        "UPDATE subscriptions SET suppressed_at = now(), suppression_reason = 'manual'
+        WHERE email = 'ursula_le_guin@gmail.com'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let messages = UnsuppressedMessages::filter(
+        &app.db_pool,
+        vec![
+            message("Ursula_Le_Guin+news@gmail.com"),
+            message("octavia_butler@gmail.com"),
+            message("someone_else@example.com"),
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(messages.len(), 2);
+}
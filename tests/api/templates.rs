@@ -0,0 +1,126 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::app;
+
+#[actix_web::test]
+async fn previews_are_only_served_when_turned_on() {
+    let app = app::spawn_app_with(|c| c.application.template_previews = false).await;
+
+    let response = app.get_template_preview("confirmation", "").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn previews_render_the_html_body_with_sample_data() {
+    let app = app::spawn_app().await;
+
+    let response = app.get_template_preview("confirmation", "").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["X-Email-Subject"], "Welcome");
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains("Hi Ursula Le Guin"));
+    assert!(body.contains(r#"href="https://example.com/subscriptions/confirm"#));
+}
+
+#[actix_web::test]
+async fn previews_render_the_text_body_on_request() {
+    let app = app::spawn_app().await;
+
+    let response = app
+        .get_template_preview("confirmation", "format=text")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains("Visit https://example.com/subscriptions/confirm"));
+}
+
+#[actix_web::test]
+async fn previews_of_unknown_templates_are_not_found() {
+    let app = app::spawn_app().await;
+
+    let response = app.get_template_preview("farewell", "").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn subscriber_names_are_escaped_in_the_html_confirmation_email() {
+    let app = app::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=Tom%20%26%20Jerry&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi Tom &amp; Jerry"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi Tom & Jerry"));
+}
+
+#[actix_web::test]
+async fn previews_render_translations_on_request() {
+    let app = app::spawn_app().await;
+
+    let response = app
+        .get_template_preview("confirmation", "format=text&locale=de")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["X-Email-Subject"], "Willkommen");
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .starts_with("Hallo Ursula Le Guin"));
+}
+
+#[actix_web::test]
+async fn previews_of_pages_render_their_html() {
+    let app = app::spawn_app().await;
+
+    let response = app.get_template_preview("confirmed", "").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Thanks, Ursula Le Guin!"));
+}
+
+#[actix_web::test]
+async fn previews_in_unsupported_locales_are_rejected() {
+    let app = app::spawn_app().await;
+
+    let response = app.get_template_preview("confirmation", "locale=xx").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
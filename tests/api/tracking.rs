@@ -0,0 +1,166 @@
+use chrono::{Duration, Utc};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::tracking::{TrackingEvent, TrackingToken};
+
+use crate::app::{self, TestApp};
+
+/// Signs up a subscriber and returns their ID
+async fn create_subscriber(app: &TestApp) -> Uuid {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriber")
+        .id
+}
+
+/// A client that lets us look at redirects instead of following them
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+fn token(subscriber_id: Uuid, issue_id: Uuid, event: TrackingEvent) -> TrackingToken {
+    TrackingToken {
+        subscriber_id,
+        issue_id,
+        event,
+        issued_at: Utc::now(),
+    }
+}
+
+#[actix_web::test]
+async fn clicks_are_recorded_and_redirected_to_the_original_url() {
+    let app = app::spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    let issue_id = Uuid::new_v4();
+    let url = "https://example.com/article?id=42";
+    let signed = token(
+        subscriber_id,
+        issue_id,
+        TrackingEvent::Click { url: url.into() },
+    )
+    .sign(&app.hmac_secret);
+
+    let response = client()
+        .get(format!("{}/t/c/{}", app.address, signed))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(response.headers()["Location"], url);
+    let saved = sqlx::query!("SELECT subscriber_id, issue_id, kind, url FROM tracking_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch tracking event");
+    assert_eq!(saved.subscriber_id, subscriber_id);
+    assert_eq!(saved.issue_id, issue_id);
+    assert_eq!(saved.kind, "click");
+    assert_eq!(saved.url.as_deref(), Some(url));
+}
+
+#[actix_web::test]
+async fn opens_are_recorded_and_served_a_pixel() {
+    let app = app::spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    let issue_id = Uuid::new_v4();
+    let signed = token(subscriber_id, issue_id, TrackingEvent::Open).sign(&app.hmac_secret);
+
+    let response = client()
+        .get(format!("{}/t/o/{}.gif", app.address, signed))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    let saved = sqlx::query!("SELECT subscriber_id, issue_id, kind, url FROM tracking_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch tracking event");
+    assert_eq!(saved.subscriber_id, subscriber_id);
+    assert_eq!(saved.issue_id, issue_id);
+    assert_eq!(saved.kind, "open");
+    assert_eq!(saved.url, None);
+}
+
+#[actix_web::test]
+async fn clicks_with_a_forged_token_are_rejected_with_404() {
+    let app = app::spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    let forged = token(
+        subscriber_id,
+        Uuid::new_v4(),
+        TrackingEvent::Click {
+            url: "https://evil.com".into(),
+        },
+    )
+    .sign(&Secret::new("not-our-secret".to_string()));
+
+    let response = client()
+        .get(format!("{}/t/c/{}", app.address, forged))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(response.headers().get("Location").is_none());
+}
+
+#[actix_web::test]
+async fn clicks_with_an_expired_token_are_rejected_with_404() {
+    let app = app::spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    let expired = TrackingToken {
+        issued_at: Utc::now() - Duration::days(365),
+        ..token(
+            subscriber_id,
+            Uuid::new_v4(),
+            TrackingEvent::Click {
+                url: "https://example.com/article?id=42".into(),
+            },
+        )
+    }
+    .sign(&app.hmac_secret);
+
+    let response = client()
+        .get(format!("{}/t/c/{}", app.address, expired))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn open_tokens_cannot_be_used_as_click_tokens() {
+    let app = app::spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    let signed = token(subscriber_id, Uuid::new_v4(), TrackingEvent::Open).sign(&app.hmac_secret);
+
+    let response = client()
+        .get(format!("{}/t/c/{}", app.address, signed))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
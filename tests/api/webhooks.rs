@@ -0,0 +1,225 @@
+use secrecy::ExposeSecret;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::app::{self, TestApp};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+/// Signs up a subscriber with `EMAIL` so there is a row for webhooks to update
+async fn create_subscriber(app: &TestApp) {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+/// The suppression reason and soft bounce count stored for `EMAIL`
+async fn suppression_state(app: &TestApp) -> (Option<String>, i32) {
+    let saved = sqlx::query!(
+        "SELECT suppression_reason, soft_bounce_count FROM subscriptions WHERE email = $1",
+        EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscriber");
+
+    (saved.suppression_reason, saved.soft_bounce_count)
+}
+
+fn bounce(bounce_type: &str) -> serde_json::Value {
+    json!({
+        "RecordType": "Bounce",
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "Email": EMAIL,
+        "BouncedAt": "2019-11-05T16:33:54.9070259Z",
+    })
+}
+
+#[actix_web::test]
+async fn webhooks_without_credentials_are_rejected_with_401() {
+    let app = app::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .json(&bounce("HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="postmark""#
+    );
+}
+
+#[actix_web::test]
+async fn webhooks_with_the_wrong_password_are_rejected_with_401() {
+    let app = app::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth(&app.postmark_webhook.username, Some("not-the-password"))
+        .json(&bounce("HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn webhooks_with_the_wrong_username_are_rejected_with_401() {
+    let app = app::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth(
+            "not-postmark",
+            Some(app.postmark_webhook.password.expose_secret()),
+        )
+        .json(&bounce("HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn malformed_webhooks_are_rejected_with_400() {
+    let app = app::spawn_app().await;
+
+    let response = app
+        .post_postmark_webhook(&json!({ "RecordType": "Bounce" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn unhandled_record_types_are_accepted() {
+    let app = app::spawn_app().await;
+
+    let response = app
+        .post_postmark_webhook(&json!({ "RecordType": "Delivery", "Recipient": EMAIL }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn a_hard_bounce_suppresses_the_subscriber() {
+    let app = app::spawn_app().await;
+    create_subscriber(&app).await;
+
+    let response = app.post_postmark_webhook(&bounce("HardBounce")).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let (reason, _) = suppression_state(&app).await;
+    assert_eq!(reason.as_deref(), Some("hard_bounce"));
+}
+
+#[actix_web::test]
+async fn bounces_find_the_subscriber_under_any_spelling_of_their_address() {
+    let app = app::spawn_app().await;
+    create_subscriber(&app).await;
+    let mut bounce = bounce("HardBounce");
+    bounce["Email"] = json!("Ursula_Le_Gu.in+news@GMAIL.com");
+
+    let response = app.post_postmark_webhook(&bounce).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let (reason, _) = suppression_state(&app).await;
+    assert_eq!(reason.as_deref(), Some("hard_bounce"));
+}
+
+#[actix_web::test]
+async fn events_about_invalid_addresses_are_ignored() {
+    let app = app::spawn_app().await;
+    create_subscriber(&app).await;
+    let mut bounce = bounce("HardBounce");
+    bounce["Email"] = json!("not an address");
+
+    let response = app.post_postmark_webhook(&bounce).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let (reason, _) = suppression_state(&app).await;
+    assert_eq!(reason, None);
+}
+
+#[actix_web::test]
+async fn a_spam_complaint_suppresses_the_subscriber() {
+    let app = app::spawn_app().await;
+    create_subscriber(&app).await;
+
+    let response = app
+        .post_postmark_webhook(&json!({
+            "RecordType": "SpamComplaint",
+            "Email": EMAIL,
+            "BouncedAt": "2019-11-05T16:33:54.9070259Z",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let (reason, _) = suppression_state(&app).await;
+    assert_eq!(reason.as_deref(), Some("spam_complaint"));
+}
+
+#[actix_web::test]
+async fn soft_bounces_suppress_the_subscriber_once_they_reach_the_threshold() {
+    let app = app::spawn_app().await;
+    create_subscriber(&app).await;
+    let threshold = app.postmark_webhook.soft_bounce_threshold;
+
+    for _ in 1..threshold {
+        app.post_postmark_webhook(&bounce("SoftBounce")).await;
+    }
+    let (reason, count) = suppression_state(&app).await;
+    assert_eq!(reason, None);
+    assert_eq!(count, threshold - 1);
+
+    app.post_postmark_webhook(&bounce("SoftBounce")).await;
+    let (reason, count) = suppression_state(&app).await;
+    assert_eq!(reason.as_deref(), Some("repeated_soft_bounces"));
+    assert_eq!(count, threshold);
+}
+
+#[actix_web::test]
+async fn subscription_changes_suppress_and_reactivate_the_subscriber() {
+    let app = app::spawn_app().await;
+    create_subscriber(&app).await;
+
+    app.post_postmark_webhook(&json!({
+        "RecordType": "SubscriptionChange",
+        "Recipient": EMAIL,
+        "SuppressSending": true,
+        "SuppressionReason": "ManualSuppression",
+    }))
+    .await;
+    let (reason, _) = suppression_state(&app).await;
+    assert_eq!(reason.as_deref(), Some("manual"));
+
+    app.post_postmark_webhook(&json!({
+        "RecordType": "SubscriptionChange",
+        "Recipient": EMAIL,
+        "SuppressSending": false,
+        "SuppressionReason": null,
+    }))
+    .await;
+    let (reason, count) = suppression_state(&app).await;
+    assert_eq!(reason, None);
+    assert_eq!(count, 0);
+}